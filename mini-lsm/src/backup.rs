@@ -0,0 +1,424 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use bytes::{Buf, BufMut};
+
+use crate::env::{write_file, Env, RandomAccessFile};
+use crate::lsm_storage::LsmStorage;
+use crate::manifest::Manifest;
+
+const META_DIR: &str = "meta";
+const SHARED_DIR: &str = "shared";
+const TMP_EXTENSION: &str = "tmp";
+
+/// Files are copied in chunks of this many bytes.
+const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// A file of a backup, stored in the shared directory under its name in the database, its size
+/// and its checksum. Ids are only unique within a database and its history, a database restored
+/// from an older backup reuses them for different content.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct BackupFile {
+    /// Name of the file in the database.
+    name: String,
+    size: u64,
+    /// CRC32 of the content of the file.
+    checksum: u32,
+}
+
+impl BackupFile {
+    /// Name of the file in the shared directory, e.g. `00005_1234_4096.sst`.
+    fn shared_name(&self) -> String {
+        let path = Path::new(&self.name);
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        format!("{}_{}_{}.{}", stem, self.checksum, self.size, ext)
+    }
+}
+
+/// The metadata of a backup point, stored in `meta/<id>`.
+///
+/// ```text
+/// | timestamp (u64) | manifest length (u32) | manifest | num files (u32) | files ... | checksum (u32) |
+/// ```
+///
+/// Each file is encoded as its name length (u16), name, size (u64) and checksum (u32). The last
+/// checksum is the CRC32 of everything before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BackupMeta {
+    /// Seconds since the Unix epoch at which the backup was created.
+    timestamp: u64,
+    /// The manifest of the database, restored as is.
+    manifest: Manifest,
+    files: Vec<BackupFile>,
+}
+
+impl BackupMeta {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.put_u64(self.timestamp);
+        let mut manifest = Vec::new();
+        self.manifest.encode(&mut manifest);
+        buf.put_u32(manifest.len() as u32);
+        buf.put_slice(&manifest);
+        buf.put_u32(self.files.len() as u32);
+        for file in &self.files {
+            buf.put_u16(file.name.len() as u16);
+            buf.put_slice(file.name.as_bytes());
+            buf.put_u64(file.size);
+            buf.put_u32(file.checksum);
+        }
+        let checksum = crc32fast::hash(&buf[start..]);
+        buf.put_u32(checksum);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 16 {
+            bail!("backup metadata truncated");
+        }
+        let (mut buf, mut checksum) = data.split_at(data.len() - 4);
+        if crc32fast::hash(buf) != checksum.get_u32() {
+            bail!("backup metadata checksum mismatch");
+        }
+        let timestamp = buf.get_u64();
+        let manifest_len = buf.get_u32() as usize;
+        if buf.remaining() < manifest_len + 4 {
+            bail!("backup metadata truncated");
+        }
+        let manifest = Manifest::decode(&buf[..manifest_len])?;
+        buf.advance(manifest_len);
+        let num_files = buf.get_u32() as usize;
+        let mut files = Vec::with_capacity(num_files);
+        for _ in 0..num_files {
+            if buf.remaining() < 2 {
+                bail!("backup metadata truncated");
+            }
+            let name_len = buf.get_u16() as usize;
+            if buf.remaining() < name_len + 12 {
+                bail!("backup metadata truncated");
+            }
+            let name = String::from_utf8(buf[..name_len].to_vec())?;
+            buf.advance(name_len);
+            files.push(BackupFile {
+                name,
+                size: buf.get_u64(),
+                checksum: buf.get_u32(),
+            });
+        }
+        Ok(Self {
+            timestamp,
+            manifest,
+            files,
+        })
+    }
+
+    fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Summary of a backup point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Id of the backup. Ids increase with every backup.
+    pub id: usize,
+    /// Seconds since the Unix epoch at which the backup was created.
+    pub timestamp: u64,
+    /// Total size of the files of the backup, including the files shared with other backups.
+    pub size: u64,
+    /// Number of files in the backup.
+    pub num_files: usize,
+}
+
+/// Copy an open file, possibly across environments, and return its size and checksum.
+fn copy_file(source: &dyn RandomAccessFile, to_env: &dyn Env, to: &Path) -> Result<(u64, u32)> {
+    let mut target = to_env.create(to)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut offset = 0;
+    while offset < source.size() {
+        let chunk = source.read_at(offset, COPY_CHUNK_SIZE.min(source.size() - offset))?;
+        hasher.update(&chunk);
+        target.write(&chunk)?;
+        offset += chunk.len() as u64;
+    }
+    target.sync()?;
+    Ok((source.size(), hasher.finalize()))
+}
+
+/// Compute the size and checksum of an open file.
+fn checksum_file(file: &dyn RandomAccessFile) -> Result<(u64, u32)> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut offset = 0;
+    while offset < file.size() {
+        let chunk = file.read_at(offset, COPY_CHUNK_SIZE.min(file.size() - offset))?;
+        hasher.update(&chunk);
+        offset += chunk.len() as u64;
+    }
+    Ok((file.size(), hasher.finalize()))
+}
+
+/// Incremental backups of a database into a directory.
+///
+/// SSTs and blob files are immutable, so each file is copied only once into the shared directory
+/// of the backups, and every backup point lists the files it uses next to the manifest of the
+/// database. Shared files are identified by their name, size and checksum, so that files of
+/// different databases, or of a database restored from an older backup, are never mixed up.
+///
+/// ```text
+/// <dir>/meta/<id>                              metadata of the backup points
+/// <dir>/shared/<id>_<checksum>_<size>.<ext>    SSTs and blob files, shared by the backup points
+/// ```
+///
+/// Files are first written under a temporary name and renamed once complete, and a backup point
+/// only exists once its metadata is written, so a crash during a backup leaves the previous
+/// backups intact.
+pub struct BackupEngine {
+    env: Arc<dyn Env>,
+    dir: PathBuf,
+}
+
+impl BackupEngine {
+    /// Open the backups in `dir`, creating the directory if it does not exist. Files left behind
+    /// by an interrupted backup are removed.
+    pub fn open(env: Arc<dyn Env>, dir: impl AsRef<Path>) -> Result<Self> {
+        let engine = Self {
+            env,
+            dir: dir.as_ref().to_path_buf(),
+        };
+        engine.env.create_dir_all(&engine.meta_dir())?;
+        engine.env.create_dir_all(&engine.shared_dir())?;
+        for path in engine.env.list(&engine.meta_dir())? {
+            if path.extension() == Some(TMP_EXTENSION.as_ref()) {
+                engine.env.delete(&path)?;
+            }
+        }
+        // Shared files of an interrupted backup are not referenced by any backup.
+        engine.remove_unreferenced_files()?;
+        Ok(engine)
+    }
+
+    fn meta_dir(&self) -> PathBuf {
+        self.dir.join(META_DIR)
+    }
+
+    fn shared_dir(&self) -> PathBuf {
+        self.dir.join(SHARED_DIR)
+    }
+
+    fn path_of_meta(&self, id: usize) -> PathBuf {
+        self.meta_dir().join(id.to_string())
+    }
+
+    /// Get the ids of the backups, in increasing order.
+    fn backup_ids(&self) -> Result<Vec<usize>> {
+        let mut ids: Vec<usize> = self
+            .env
+            .list(&self.meta_dir())?
+            .iter()
+            .filter_map(|path| path.file_name()?.to_str()?.parse().ok())
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn read_meta(&self, id: usize) -> Result<BackupMeta> {
+        let file = self
+            .env
+            .open(&self.path_of_meta(id))
+            .with_context(|| format!("backup {} not found", id))?;
+        BackupMeta::decode(&file.read_at(0, file.size())?)
+            .with_context(|| format!("failed to read backup {}", id))
+    }
+
+    /// Write atomically to a file: write a temporary file, sync it and rename it over the target.
+    fn write_atomically(&self, path: &Path, data: &[u8]) -> Result<()> {
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+        write_file(self.env.as_ref(), &tmp_path, data)?;
+        self.env.rename(&tmp_path, path)?;
+        self.env.sync_dir(path.parent().unwrap())
+    }
+
+    /// Create a new backup point of the storage, copying the files that are not in the backup
+    /// directory yet. Like [`LsmStorage::checkpoint`], the memtables are flushed first, and writes
+    /// that happen after the flush are not part of the backup. The files are opened with the flush
+    /// lock held, and copied after it is released, so that the storage does not wait for the copy.
+    /// The shared files reused from older backups are verified against their checksum.
+    pub fn create_backup(&mut self, storage: &LsmStorage) -> Result<BackupInfo> {
+        // Only files referenced by a complete backup are known to be fully copied.
+        let mut known = HashSet::new();
+        let ids = self.backup_ids()?;
+        for id in &ids {
+            known.extend(self.read_meta(*id)?.files);
+        }
+        let id = ids.last().map_or(1, |id| id + 1);
+
+        storage.sync()?;
+        let (manifest, sources) = {
+            // Files are only removed from the storage once they have left the snapshot, which
+            // requires the flush lock. Open files stay readable after they are removed.
+            let _flush_lock = storage.flush_lock.lock();
+            let snapshot = storage.inner.read().clone();
+            let manifest = Manifest::from_snapshot(&snapshot, storage.peek_next_sst_id());
+            let paths = manifest.sst_ids().map(|id| storage.path_of_sst(id)).chain(
+                manifest
+                    .blob_files
+                    .iter()
+                    .map(|id| storage.path_of_blob(*id)),
+            );
+            let sources = paths
+                .map(|path| {
+                    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+                    Ok((name, storage.env().open(&path)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            (manifest, sources)
+        };
+        let mut files = Vec::new();
+        for (name, source) in sources {
+            let (size, checksum) = checksum_file(source.as_ref())?;
+            let file = BackupFile {
+                name,
+                size,
+                checksum,
+            };
+            let target = self.shared_dir().join(file.shared_name());
+            if known.contains(&file) {
+                let shared = self.env.open(&target)?;
+                if checksum_file(shared.as_ref())? != (size, checksum) {
+                    bail!("shared file {} is corrupted", target.display());
+                }
+            } else {
+                let tmp_path = target.with_extension(TMP_EXTENSION);
+                if copy_file(source.as_ref(), self.env.as_ref(), &tmp_path)? != (size, checksum) {
+                    bail!("{} changed while it was copied", file.name);
+                }
+                self.env.rename(&tmp_path, &target)?;
+            }
+            files.push(file);
+        }
+        self.env.sync_dir(&self.shared_dir())?;
+
+        let meta = BackupMeta {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            manifest,
+            files,
+        };
+        let mut buf = Vec::new();
+        meta.encode(&mut buf);
+        self.write_atomically(&self.path_of_meta(id), &buf)?;
+        Ok(BackupInfo {
+            id,
+            timestamp: meta.timestamp,
+            size: meta.size(),
+            num_files: meta.files.len(),
+        })
+    }
+
+    /// List the backups, from the oldest to the newest.
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        self.backup_ids()?
+            .into_iter()
+            .map(|id| {
+                let meta = self.read_meta(id)?;
+                Ok(BackupInfo {
+                    id,
+                    timestamp: meta.timestamp,
+                    size: meta.size(),
+                    num_files: meta.files.len(),
+                })
+            })
+            .collect()
+    }
+
+    /// Check that all files of a backup are present with their recorded size and checksum.
+    pub fn verify_backup(&self, id: usize) -> Result<()> {
+        for file in self.read_meta(id)?.files {
+            let path = self.shared_dir().join(file.shared_name());
+            let (size, checksum) = self
+                .env
+                .open(&path)
+                .and_then(|file| checksum_file(file.as_ref()))
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if size != file.size || checksum != file.checksum {
+                bail!("file {} of backup {} is corrupted", file.name, id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete a backup, and the files no other backup uses.
+    pub fn delete_backup(&mut self, id: usize) -> Result<()> {
+        let path = self.path_of_meta(id);
+        if !self.env.list(&self.meta_dir())?.contains(&path) {
+            bail!("backup {} not found", id);
+        }
+        self.env.delete(&path)?;
+        self.env.sync_dir(&self.meta_dir())?;
+        self.remove_unreferenced_files()
+    }
+
+    /// Delete all but the `num_backups_to_keep` newest backups. Returns the number of deleted
+    /// backups.
+    pub fn purge_old_backups(&mut self, num_backups_to_keep: usize) -> Result<usize> {
+        let ids = self.backup_ids()?;
+        let num_purged = ids.len().saturating_sub(num_backups_to_keep);
+        for id in &ids[..num_purged] {
+            self.env.delete(&self.path_of_meta(*id))?;
+        }
+        self.env.sync_dir(&self.meta_dir())?;
+        self.remove_unreferenced_files()?;
+        Ok(num_purged)
+    }
+
+    /// Remove the shared files that no backup uses.
+    fn remove_unreferenced_files(&self) -> Result<()> {
+        let mut live = HashSet::new();
+        for id in self.backup_ids()? {
+            for file in self.read_meta(id)?.files {
+                live.insert(self.shared_dir().join(file.shared_name()));
+            }
+        }
+        for path in self.env.list(&self.shared_dir())? {
+            if !live.contains(&path) {
+                self.env.delete(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restore a backup into `dir`, which must not exist or be empty, in the environment of the
+    /// backups. The checksums of the files are verified while they are copied. The restored
+    /// directory can be opened as a database.
+    pub fn restore_backup(&self, id: usize, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        let meta = self.read_meta(id)?;
+        self.env.create_dir_all(dir)?;
+        if !self.env.list(dir)?.is_empty() {
+            bail!("restore directory {} is not empty", dir.display());
+        }
+        for file in &meta.files {
+            let from = self.shared_dir().join(file.shared_name());
+            let (size, checksum) = self
+                .env
+                .open(&from)
+                .and_then(|source| {
+                    copy_file(source.as_ref(), self.env.as_ref(), &dir.join(&file.name))
+                })
+                .with_context(|| format!("failed to copy {}", from.display()))?;
+            if size != file.size || checksum != file.checksum {
+                bail!("file {} of backup {} is corrupted", file.name, id);
+            }
+        }
+        // The manifest makes the restored database complete, and syncs the directory.
+        meta.manifest.write(self.env.as_ref(), dir)
+    }
+}
+
+#[cfg(test)]
+mod tests;
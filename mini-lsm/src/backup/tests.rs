@@ -0,0 +1,43 @@
+use super::*;
+
+fn meta() -> BackupMeta {
+    BackupMeta {
+        timestamp: 1_700_000_000,
+        manifest: Manifest {
+            next_sst_id: 10,
+            l0_sstables: vec![5, 7],
+            levels: vec![vec![1, 2]],
+            blob_files: vec![4],
+        },
+        files: [("00005.sst", 100), ("00007.sst", 200), ("00004.blob", 0)]
+            .into_iter()
+            .map(|(name, size)| BackupFile {
+                name: name.to_string(),
+                size,
+                checksum: size as u32 * 7,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_backup_meta_encode_decode() {
+    let mut buf = Vec::new();
+    meta().encode(&mut buf);
+    assert_eq!(BackupMeta::decode(&buf).unwrap(), meta());
+    assert_eq!(meta().size(), 300);
+}
+
+#[test]
+fn test_backup_meta_corruption() {
+    let mut buf = Vec::new();
+    meta().encode(&mut buf);
+    for len in 0..buf.len() {
+        assert!(BackupMeta::decode(&buf[..len]).is_err());
+    }
+    for idx in 0..buf.len() {
+        let mut corrupted = buf.clone();
+        corrupted[idx] ^= 0x10;
+        assert!(BackupMeta::decode(&corrupted).is_err());
+    }
+}
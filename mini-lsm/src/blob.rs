@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::env::Env;
+use crate::iterators::StorageIterator;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::table::FileObject;
+
+/// Tag of a value stored inline in the SST.
+pub const VALUE_INLINE: u8 = 0;
+/// Tag of a value stored in a blob file, the SST only keeps a [`BlobPointer`].
+pub const VALUE_BLOB: u8 = 1;
+
+/// Points to a value in a blob file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobPointer {
+    /// Id of the blob file.
+    pub file_id: usize,
+    /// Offset of the value in the blob file.
+    pub offset: u64,
+    /// Length of the value.
+    pub len: u64,
+}
+
+impl BlobPointer {
+    const ENCODED_SIZE: usize = std::mem::size_of::<u32>() + std::mem::size_of::<u64>() * 2;
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u32(self.file_id as u32);
+        buf.put_u64(self.offset);
+        buf.put_u64(self.len);
+    }
+
+    pub fn decode(mut buf: impl Buf) -> Result<Self> {
+        if buf.remaining() != Self::ENCODED_SIZE {
+            bail!("invalid blob pointer of {} bytes", buf.remaining());
+        }
+        Ok(Self {
+            file_id: buf.get_u32() as usize,
+            offset: buf.get_u64(),
+            len: buf.get_u64(),
+        })
+    }
+}
+
+/// A non-empty value as stored in an SST: a one-byte tag followed by either the value itself or a
+/// pointer into a blob file. Tombstones are stored as empty values without a tag.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoredValue<'a> {
+    Inline(&'a [u8]),
+    Blob(BlobPointer),
+}
+
+impl<'a> StoredValue<'a> {
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            StoredValue::Inline(value) => {
+                buf.put_u8(VALUE_INLINE);
+                buf.put_slice(value);
+            }
+            StoredValue::Blob(pointer) => {
+                buf.put_u8(VALUE_BLOB);
+                pointer.encode(buf);
+            }
+        }
+    }
+
+    /// Length of the user value.
+    pub fn value_len(&self) -> usize {
+        match self {
+            StoredValue::Inline(value) => value.len(),
+            StoredValue::Blob(pointer) => pointer.len as usize,
+        }
+    }
+
+    pub fn decode(raw: &'a [u8]) -> Result<Self> {
+        match raw.first() {
+            Some(&VALUE_INLINE) => Ok(StoredValue::Inline(&raw[1..])),
+            Some(&VALUE_BLOB) => Ok(StoredValue::Blob(BlobPointer::decode(&raw[1..])?)),
+            Some(tag) => bail!("unknown value tag {}", tag),
+            None => bail!("cannot decode a tombstone"),
+        }
+    }
+}
+
+/// All blob files visible in a snapshot of the storage.
+pub type BlobFiles = HashMap<usize, Arc<BlobFile>>;
+
+/// Resolves a value read from an SST into the user value. Returns `None` for tombstones. Inline
+/// values share the buffer of `raw`.
+pub fn resolve_value(blob_files: &BlobFiles, raw: &Bytes) -> Result<Option<Bytes>> {
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    match StoredValue::decode(raw)? {
+        StoredValue::Inline(_) => Ok(Some(raw.slice(1..))),
+        StoredValue::Blob(pointer) => read_blob_value(blob_files, &pointer).map(Some),
+    }
+}
+
+fn read_blob_value(blob_files: &BlobFiles, pointer: &BlobPointer) -> Result<Bytes> {
+    blob_files
+        .get(&pointer.file_id)
+        .ok_or_else(|| anyhow!("blob file {} not found", pointer.file_id))?
+        .read_value(pointer)
+}
+
+/// A key-value record in a blob file.
+pub struct BlobRecord {
+    pub key: Bytes,
+    pub pointer: BlobPointer,
+}
+
+/// Builds a blob file. The file is a sequence of records:
+///
+/// ```text
+/// | key_len (u16) | key | value_len (u32) | value | ...
+/// ```
+///
+/// Keys are kept in the file so that the garbage collector can check whether a value is still
+/// referenced by the LSM tree.
+pub struct BlobFileBuilder {
+    id: usize,
+    data: Vec<u8>,
+    /// Limits the rate at which the file is written, if set.
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+}
+
+impl BlobFileBuilder {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            data: Vec::new(),
+            rate_limiter: None,
+        }
+    }
+
+    /// Write the file in chunks granted by a rate limiter in the lane of `priority`.
+    pub fn with_rate_limiter(
+        mut self,
+        rate_limiter: Arc<RateLimiter>,
+        priority: IoPriority,
+    ) -> Self {
+        self.rate_limiter = Some((rate_limiter, priority));
+        self
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Appends a key-value pair and returns the pointer to the value.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> BlobPointer {
+        self.data.put_u16(key.len() as u16);
+        self.data.put_slice(key);
+        self.data.put_u32(value.len() as u32);
+        let offset = self.data.len() as u64;
+        self.data.put_slice(value);
+        BlobPointer {
+            file_id: self.id,
+            offset,
+            len: value.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Writes the blob file to the given path.
+    pub fn build(self, env: &dyn Env, path: impl AsRef<Path>) -> Result<BlobFile> {
+        let file = match self.rate_limiter {
+            Some((rate_limiter, priority)) => FileObject::create_with_rate_limiter(
+                env,
+                path.as_ref(),
+                self.data,
+                &rate_limiter,
+                priority,
+            )?,
+            None => FileObject::create(env, path.as_ref(), self.data)?,
+        };
+        Ok(BlobFile { id: self.id, file })
+    }
+}
+
+/// An immutable file holding separated values.
+pub struct BlobFile {
+    id: usize,
+    file: FileObject,
+}
+
+impl BlobFile {
+    /// Open an existing blob file.
+    pub fn open(env: &dyn Env, id: usize, path: &Path) -> Result<Self> {
+        Ok(BlobFile {
+            id,
+            file: FileObject::open(env, path)?,
+        })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Size of the blob file in bytes.
+    pub fn size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// Read the value a pointer refers to.
+    pub fn read_value(&self, pointer: &BlobPointer) -> Result<Bytes> {
+        debug_assert_eq!(pointer.file_id, self.id);
+        self.file.read(pointer.offset, pointer.len)
+    }
+
+    /// Decode all records in the file.
+    pub fn records(&self) -> Result<Vec<BlobRecord>> {
+        let data = self.file.read(0, self.file.size())?;
+        let mut buf = &data[..];
+        let mut records = Vec::new();
+        while buf.has_remaining() {
+            let key_len = buf.get_u16() as usize;
+            let key = data.slice_ref(&buf[..key_len]);
+            buf.advance(key_len);
+            let len = buf.get_u32() as u64;
+            let offset = (data.len() - buf.remaining()) as u64;
+            buf.advance(len as usize);
+            records.push(BlobRecord {
+                key,
+                pointer: BlobPointer {
+                    file_id: self.id,
+                    offset,
+                    len,
+                },
+            });
+        }
+        Ok(records)
+    }
+}
+
+/// Wraps an iterator over SSTs and resolves blob pointers into the values they point to. Tombstones
+/// are passed through as empty values.
+///
+/// Entries past `end_bound` are not resolved. The scan skips the tables outside of its range, so an
+/// entry past the range may be shadowed by a newer version in a skipped table, and point into a
+/// blob file that has been collected.
+pub struct BlobResolveIterator<I: StorageIterator> {
+    iter: I,
+    blob_files: Arc<BlobFiles>,
+    end_bound: Bound<Bytes>,
+    /// The value read from a blob file, if the current entry is separated.
+    blob_value: Option<Bytes>,
+}
+
+impl<I: StorageIterator> BlobResolveIterator<I> {
+    pub fn create(iter: I, blob_files: Arc<BlobFiles>, end_bound: Bound<Bytes>) -> Result<Self> {
+        let mut iter = Self {
+            iter,
+            blob_files,
+            end_bound,
+            blob_value: None,
+        };
+        iter.resolve()?;
+        Ok(iter)
+    }
+
+    fn resolve(&mut self) -> Result<()> {
+        self.blob_value = None;
+        if !self.iter.is_valid() || self.iter.value().is_empty() {
+            return Ok(());
+        }
+        let past_end = match self.end_bound.as_ref() {
+            Bound::Included(key) => self.iter.key() > key.as_ref(),
+            Bound::Excluded(key) => self.iter.key() >= key.as_ref(),
+            Bound::Unbounded => false,
+        };
+        if past_end {
+            return Ok(());
+        }
+        if let StoredValue::Blob(pointer) = StoredValue::decode(self.iter.value())? {
+            self.blob_value = Some(read_blob_value(&self.blob_files, &pointer)?);
+        }
+        Ok(())
+    }
+}
+
+impl<I: StorageIterator> StorageIterator for BlobResolveIterator<I> {
+    fn value(&self) -> &[u8] {
+        if let Some(ref value) = self.blob_value {
+            return value;
+        }
+        let raw = self.iter.value();
+        if raw.is_empty() {
+            raw
+        } else {
+            &raw[1..]
+        }
+    }
+
+    fn key(&self) -> &[u8] {
+        self.iter.key()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()?;
+        self.resolve()
+    }
+}
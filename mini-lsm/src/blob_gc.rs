@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::blob::{BlobRecord, StoredValue};
+use crate::event_listener::BlobFileDeletionInfo;
+use crate::lsm_storage::{LsmStorage, LsmStorageInner};
+
+impl LsmStorage {
+    /// Reclaim space from blob files. A blob file is collected when at least `discard_ratio` of its
+    /// value bytes belong to overwritten or deleted keys. The values still in use are put back into
+    /// the memtable, which moves them to a new blob file on flush, and the old file is removed
+    /// after that. The memtables are flushed before any file is removed, so the collection also
+    /// persists the writes made before it. Returns the number of removed blob files.
+    pub fn gc_blob_files(&self, discard_ratio: f64) -> Result<usize> {
+        let snapshot = {
+            let guard = self.inner.read();
+            Arc::clone(&guard)
+        }; // drop global lock here
+
+        let mut obsolete = Vec::new();
+        let mut live = Vec::new();
+        for blob_file in snapshot.blob_files.values() {
+            let records = blob_file.records()?;
+            let total_bytes: u64 = records.iter().map(|record| record.pointer.len).sum();
+            let mut live_records = Vec::new();
+            let mut garbage_bytes = 0;
+            for record in records {
+                if self.is_blob_record_live(&snapshot, &record)? {
+                    live_records.push(record);
+                } else {
+                    garbage_bytes += record.pointer.len;
+                }
+            }
+            if total_bytes > 0 && (garbage_bytes as f64) < total_bytes as f64 * discard_ratio {
+                continue;
+            }
+            for record in live_records {
+                let value = blob_file.read_value(&record.pointer)?;
+                live.push((record.key, value));
+            }
+            obsolete.push(blob_file.id());
+        }
+
+        if obsolete.is_empty() {
+            return Ok(0);
+        }
+        self.write_back_live_values(&snapshot, live)?;
+        // Persist the rewritten values before dropping the old blob files. The values found dead
+        // or skipped because of a newer version in a memtable are only dead once that version is
+        // durable, so the memtables are flushed even if nothing has been written back.
+        self.sync()?;
+        {
+            let _flush_lock = self.flush_lock.lock();
+            let mut snapshot = self.inner.read().as_ref().clone();
+            let mut blob_files = snapshot.blob_files.as_ref().clone();
+            for id in &obsolete {
+                blob_files.remove(id);
+            }
+            snapshot.blob_files = blob_files.into();
+            // The files can only be removed once the manifest no longer refers to them.
+            self.write_manifest(&snapshot)?;
+            *self.inner.write() = snapshot.into();
+        }
+        // Readers holding an older snapshot keep the file open, so it can be unlinked right away.
+        for &blob_file_id in &obsolete {
+            let path = self.path_of_blob(blob_file_id);
+            self.env().delete(&path)?;
+            let info = BlobFileDeletionInfo { blob_file_id, path };
+            self.notify(|listener| listener.on_blob_file_deleted(&info));
+        }
+        Ok(obsolete.len())
+    }
+
+    /// Put the values still in use back into the memtable, skipping the keys written since
+    /// `snapshot` was taken.
+    fn write_back_live_values(
+        &self,
+        snapshot: &LsmStorageInner,
+        live: Vec<(Bytes, Bytes)>,
+    ) -> Result<()> {
+        if live.is_empty() {
+            return Ok(());
+        }
+        // Hold the flush lock so that no new table can hide a newer version of a key. The tables
+        // added since the snapshot are probed before taking the write lock.
+        let _flush_lock = self.flush_lock.lock();
+        let current = self.inner.read().as_ref().clone();
+        let old_tables: HashSet<usize> = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flatten())
+            .map(|table| table.sst_id())
+            .collect();
+        let new_tables: Vec<_> = current
+            .l0_sstables
+            .iter()
+            .chain(current.levels.iter().flatten())
+            .filter(|table| !old_tables.contains(&table.sst_id()))
+            .collect();
+        let mut candidates = Vec::with_capacity(live.len());
+        'live: for (key, value) in live {
+            for table in &new_tables {
+                if table.may_contain_key(&key) && self.open_sst(table)?.get(&key)?.is_some() {
+                    continue 'live;
+                }
+            }
+            candidates.push((key, value));
+        }
+
+        // Hold the write lock so that no user write can interleave between checking the memtables
+        // and writing a value back.
+        let guard = self.inner.write();
+        for (key, value) in candidates {
+            if guard.memtable.get(&key).is_some()
+                || guard
+                    .imm_memtables
+                    .iter()
+                    .any(|memtable| memtable.get(&key).is_some())
+            {
+                continue;
+            }
+            guard.memtable.put(&key, &value);
+        }
+        Ok(())
+    }
+
+    /// Check whether the newest version of the record's key still points to the record.
+    fn is_blob_record_live(&self, snapshot: &LsmStorageInner, record: &BlobRecord) -> Result<bool> {
+        if snapshot.memtable.get(&record.key).is_some()
+            || snapshot
+                .imm_memtables
+                .iter()
+                .any(|memtable| memtable.get(&record.key).is_some())
+        {
+            return Ok(false);
+        }
+        match self.get_from_sstables(snapshot, &record.key)? {
+            Some(raw) if !raw.is_empty() => {
+                Ok(StoredValue::decode(&raw)? == StoredValue::Blob(record.pointer))
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -1,44 +1,126 @@
 mod builder;
+mod hash_index;
 mod iterator;
 
+use anyhow::{bail, Result};
 pub use builder::BlockBuilder;
 use bytes::{Buf, BufMut, Bytes};
+use hash_index::{BUCKET_COLLISION, BUCKET_EMPTY, HASH_INDEX_FLAG};
 pub use iterator::BlockIterator;
 
 pub const SIZEOF_U16: usize = std::mem::size_of::<u16>();
 
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
+///
+/// A block may carry a hash index for point lookups, in which case it is encoded as:
+///
+/// ```text
+/// | data | offsets | buckets (u16 each) | number of buckets (u16) | number of entries | flag (u16) |
+/// ```
 pub struct Block {
-    data: Vec<u8>,
+    data: Bytes,
     offsets: Vec<u16>,
+    /// Buckets of the hash index, empty if the block has no hash index.
+    buckets: Vec<u16>,
 }
 
 impl Block {
     pub fn encode(&self) -> Bytes {
-        let mut buf = self.data.clone();
+        let mut buf = self.data.to_vec();
         let offsets_len = self.offsets.len();
         for offset in &self.offsets {
             buf.put_u16(*offset);
         }
-        // Adds number of elements at the end of the block
-        buf.put_u16(offsets_len as u16);
+        if self.buckets.is_empty() {
+            // Adds number of elements at the end of the block
+            buf.put_u16(offsets_len as u16);
+        } else {
+            for bucket in &self.buckets {
+                buf.put_u16(*bucket);
+            }
+            buf.put_u16(self.buckets.len() as u16);
+            buf.put_u16(offsets_len as u16 | HASH_INDEX_FLAG);
+        }
         buf.into()
     }
 
-    pub fn decode(data: &[u8]) -> Self {
+    /// Decode a block. The entries are not copied, the block shares the buffer of `data`. Fails if
+    /// the offsets, the hash index or the entries do not fit in `data`, so that iterating on the
+    /// block never reads out of bounds.
+    pub fn decode(data: Bytes) -> Result<Self> {
+        if data.len() < SIZEOF_U16 {
+            bail!("block of {} bytes is too small", data.len());
+        }
         // get number of elements in the block
-        let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
-        let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
-        let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
+        let footer = (&data[data.len() - SIZEOF_U16..]).get_u16();
+        let entry_offsets_len = (footer & !HASH_INDEX_FLAG) as usize;
+        let mut offsets_end = data.len() - SIZEOF_U16;
+        let buckets: Vec<u16> = if footer & HASH_INDEX_FLAG != 0 {
+            if offsets_end < SIZEOF_U16 {
+                bail!("block hash index is truncated");
+            }
+            let num_buckets = (&data[offsets_end - SIZEOF_U16..]).get_u16() as usize;
+            offsets_end -= SIZEOF_U16;
+            if num_buckets == 0 || offsets_end < num_buckets * SIZEOF_U16 {
+                bail!("block hash index is truncated");
+            }
+            let buckets_raw = &data[offsets_end - num_buckets * SIZEOF_U16..offsets_end];
+            offsets_end -= num_buckets * SIZEOF_U16;
+            buckets_raw
+                .chunks(SIZEOF_U16)
+                .map(|mut x| x.get_u16())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if offsets_end < entry_offsets_len * SIZEOF_U16 {
+            bail!("block offsets are truncated");
+        }
+        let data_end = offsets_end - entry_offsets_len * SIZEOF_U16;
+        let offsets_raw = &data[data_end..offsets_end];
         // get offset array
-        let offsets = offsets_raw
+        let offsets: Vec<u16> = offsets_raw
             .chunks(SIZEOF_U16)
             .map(|mut x| x.get_u16())
             .collect();
         // retrieve data
-        let data = data[0..data_end].to_vec();
-        Self { data, offsets }
+        let data = data.slice(0..data_end);
+        for &offset in &offsets {
+            let mut entry = data.get(offset as usize..).unwrap_or_default();
+            if entry.len() < SIZEOF_U16 {
+                bail!("block entry at offset {} is out of bounds", offset);
+            }
+            let key_len = entry.get_u16() as usize;
+            if key_len == 0 || entry.len() < key_len + SIZEOF_U16 {
+                bail!("block entry at offset {} is out of bounds", offset);
+            }
+            entry.advance(key_len);
+            let value_len = entry.get_u16() as usize;
+            if entry.len() < value_len {
+                bail!("block entry at offset {} is out of bounds", offset);
+            }
+        }
+        if let Some(bucket) = buckets.iter().find(|&&bucket| {
+            bucket != BUCKET_EMPTY && bucket != BUCKET_COLLISION && bucket as usize >= offsets.len()
+        }) {
+            bail!("block hash index points to entry {} out of bounds", bucket);
+        }
+        Ok(Self {
+            data,
+            offsets,
+            buckets,
+        })
+    }
+
+    /// Get the size of the block in memory, in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len() + (self.offsets.len() + self.buckets.len()) * SIZEOF_U16
+    }
+
+    /// Check if the block has a hash index.
+    pub fn has_hash_index(&self) -> bool {
+        !self.buckets.is_empty()
     }
 }
 
@@ -1,5 +1,6 @@
 use bytes::BufMut;
 
+use super::hash_index::{build_buckets, num_buckets};
 use super::{Block, SIZEOF_U16};
 
 /// Builds a block.
@@ -10,6 +11,8 @@ pub struct BlockBuilder {
     data: Vec<u8>,
     /// The expected block size.
     block_size: usize,
+    /// Whether to build a hash index for point lookups.
+    hash_index: bool,
 }
 
 impl BlockBuilder {
@@ -19,6 +22,16 @@ impl BlockBuilder {
             offsets: Vec::new(),
             data: Vec::new(),
             block_size,
+            hash_index: false,
+        }
+    }
+
+    /// Creates a new block builder that appends a hash index to the block, so that point lookups
+    /// can find a key without a binary search.
+    pub fn new_with_hash_index(block_size: usize) -> Self {
+        Self {
+            hash_index: true,
+            ..Self::new(block_size)
         }
     }
 
@@ -27,11 +40,22 @@ impl BlockBuilder {
         /* key-value pairs */
     }
 
+    /// Size of the hash index of a block with `num_entries` entries.
+    fn hash_index_size(&self, num_entries: usize) -> usize {
+        if self.hash_index {
+            num_buckets(num_entries) * SIZEOF_U16 /* buckets */ + SIZEOF_U16 /* number of buckets */
+        } else {
+            0
+        }
+    }
+
     /// Adds a key-value pair to the block. Returns false when the block is full.
     #[must_use]
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
-        if self.estimated_size() + key.len() + value.len() + SIZEOF_U16 * 3 /* key_len, value_len and offset */ > self.block_size
+        if self.estimated_size() + key.len() + value.len() + SIZEOF_U16 * 3 /* key_len, value_len and offset */
+            + self.hash_index_size(self.offsets.len() + 1)
+            > self.block_size
             && !self.is_empty()
         {
             return false;
@@ -59,9 +83,19 @@ impl BlockBuilder {
         if self.is_empty() {
             panic!("block should not be empty");
         }
+        let buckets = if self.hash_index {
+            build_buckets(self.offsets.iter().map(|offset| {
+                let entry = &self.data[*offset as usize..];
+                let key_len = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+                &entry[SIZEOF_U16..SIZEOF_U16 + key_len]
+            }))
+        } else {
+            Vec::new()
+        };
         Block {
-            data: self.data,
+            data: self.data.into(),
             offsets: self.offsets,
+            buckets,
         }
     }
 }
@@ -0,0 +1,43 @@
+/// Marks an empty bucket.
+pub const BUCKET_EMPTY: u16 = u16::MAX;
+/// Marks a bucket that more than one key hashes into.
+pub const BUCKET_COLLISION: u16 = u16::MAX - 1;
+/// Set in the entry count at the end of a block if the block has a hash index.
+pub const HASH_INDEX_FLAG: u16 = 1 << 15;
+
+/// Hash a key with 32-bit FNV-1a. The hash is part of the on-disk format, so it must not depend on
+/// the platform or the Rust version.
+pub fn key_hash(key: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in key {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+/// Get the number of buckets for a block of `num_entries` entries, at a load factor of 0.75.
+pub fn num_buckets(num_entries: usize) -> usize {
+    num_entries * 4 / 3 + 1
+}
+
+/// Get the bucket a key belongs to.
+pub fn bucket_of(key: &[u8], num_buckets: usize) -> usize {
+    key_hash(key) as usize % num_buckets
+}
+
+/// Build the buckets for the given keys, in the order of the entries in the block. Each bucket
+/// holds the index of the only entry hashed into it, [`BUCKET_EMPTY`] or [`BUCKET_COLLISION`].
+pub fn build_buckets<'a>(keys: impl ExactSizeIterator<Item = &'a [u8]>) -> Vec<u16> {
+    let mut buckets = vec![BUCKET_EMPTY; num_buckets(keys.len())];
+    let num_buckets = buckets.len();
+    for (idx, key) in keys.enumerate() {
+        let bucket = &mut buckets[bucket_of(key, num_buckets)];
+        *bucket = if *bucket == BUCKET_EMPTY {
+            idx as u16
+        } else {
+            BUCKET_COLLISION
+        };
+    }
+    buckets
+}
@@ -1,17 +1,19 @@
+use std::ops::Range;
 use std::sync::Arc;
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 
-use super::Block;
+use super::hash_index::{bucket_of, BUCKET_COLLISION, BUCKET_EMPTY};
+use super::{Block, SIZEOF_U16};
 
 /// Iterates on a block.
 pub struct BlockIterator {
     /// reference to the block
     block: Arc<Block>,
-    /// the current key at the iterator position
-    key: Vec<u8>,
-    /// the current value at the iterator position
-    value: Vec<u8>,
+    /// the range of the current key in the block data, empty if the iterator is invalid
+    key: Range<usize>,
+    /// the range of the current value in the block data
+    value: Range<usize>,
     /// the current index at the iterator position
     idx: usize,
 }
@@ -20,8 +22,8 @@ impl BlockIterator {
     fn new(block: Arc<Block>) -> Self {
         Self {
             block,
-            key: Vec::new(),
-            value: Vec::new(),
+            key: 0..0,
+            value: 0..0,
             idx: 0,
         }
     }
@@ -43,13 +45,25 @@ impl BlockIterator {
     /// Returns the key of the current entry.
     pub fn key(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.key
+        &self.block.data[self.key.clone()]
     }
 
     /// Returns the value of the current entry.
     pub fn value(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.value
+        &self.block.data[self.value.clone()]
+    }
+
+    /// Returns the key of the current entry, sharing the buffer of the block.
+    pub fn key_bytes(&self) -> Bytes {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.block.data.slice(self.key.clone())
+    }
+
+    /// Returns the value of the current entry, sharing the buffer of the block.
+    pub fn value_bytes(&self) -> Bytes {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.block.data.slice(self.value.clone())
     }
 
     /// Returns true if the iterator is valid.
@@ -63,10 +77,10 @@ impl BlockIterator {
     }
 
     /// Seeks to the idx-th key in the block.
-    fn seek_to(&mut self, idx: usize) {
+    pub(crate) fn seek_to(&mut self, idx: usize) {
         if idx >= self.block.offsets.len() {
-            self.key.clear();
-            self.value.clear();
+            self.key = 0..0;
+            self.value = 0..0;
             return;
         }
         let offset = self.block.offsets[idx] as usize;
@@ -87,15 +101,41 @@ impl BlockIterator {
         // Since `get_u16()` will automatically move the ptr 2 bytes ahead here,
         // we don't need to manually advance it
         let key_len = entry.get_u16() as usize;
-        let key = entry[..key_len].to_vec();
+        let key_start = offset + SIZEOF_U16;
+        self.key = key_start..key_start + key_len;
         entry.advance(key_len);
-        self.key.clear();
-        self.key.extend(key);
         let value_len = entry.get_u16() as usize;
-        let value = entry[..value_len].to_vec();
-        entry.advance(value_len);
-        self.value.clear();
-        self.value.extend(value);
+        let value_start = self.key.end + SIZEOF_U16;
+        self.value = value_start..value_start + value_len;
+    }
+
+    /// Seek to exactly `key` for a point lookup, and return whether the key is found. The iterator
+    /// is invalid if the key is not in the block. Uses the hash index of the block if there is one,
+    /// and falls back to binary search otherwise.
+    pub fn seek_to_exact_key(&mut self, key: &[u8]) -> bool {
+        let bucket = if self.block.has_hash_index() {
+            self.block.buckets[bucket_of(key, self.block.buckets.len())]
+        } else {
+            BUCKET_COLLISION
+        };
+        match bucket {
+            // No key in the block hashes into the bucket.
+            BUCKET_EMPTY => {}
+            BUCKET_COLLISION => {
+                self.seek_to_key(key);
+                if self.is_valid() && self.key() == key {
+                    return true;
+                }
+            }
+            idx => {
+                self.seek_to(idx as usize);
+                if self.key() == key {
+                    return true;
+                }
+            }
+        }
+        self.seek_to(self.block.offsets.len());
+        false
     }
 
     /// Seek to the first key that is >= `key`.
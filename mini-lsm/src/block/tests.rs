@@ -56,11 +56,33 @@ fn test_block_encode() {
 fn test_block_decode() {
     let block = generate_block();
     let encoded = block.encode();
-    let decoded_block = Block::decode(&encoded);
+    let decoded_block = Block::decode(encoded).unwrap();
     assert_eq!(block.offsets, decoded_block.offsets);
     assert_eq!(block.data, decoded_block.data);
 }
 
+#[test]
+fn test_block_decode_corrupted() {
+    let encoded = generate_block().encode();
+    assert!(Block::decode(Bytes::new()).is_err());
+    // Offsets pointing past the data.
+    let mut corrupted = encoded.to_vec();
+    let offsets_start = corrupted.len() - SIZEOF_U16 * (num_of_keys() + 1);
+    corrupted[offsets_start..offsets_start + SIZEOF_U16].copy_from_slice(&u16::MAX.to_be_bytes());
+    assert!(Block::decode(corrupted.into()).is_err());
+    // Garbage in every position must either decode to a block that can be iterated, or fail.
+    for pos in 0..encoded.len() {
+        let mut corrupted = encoded.to_vec();
+        corrupted[pos] = 0xff;
+        if let Ok(block) = Block::decode(corrupted.into()) {
+            let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(block));
+            while iter.is_valid() {
+                iter.next();
+            }
+        }
+    }
+}
+
 fn as_bytes(x: &[u8]) -> Bytes {
     Bytes::copy_from_slice(x)
 }
@@ -120,3 +142,67 @@ fn test_block_seek_key() {
         iter.seek_to_key(b"k");
     }
 }
+
+fn generate_block_with_hash_index() -> Block {
+    let mut builder = BlockBuilder::new_with_hash_index(10000);
+    for idx in 0..num_of_keys() {
+        assert!(builder.add(&key_of(idx), &value_of(idx)));
+    }
+    builder.build()
+}
+
+#[test]
+fn test_block_hash_index_encode_decode() {
+    let block = generate_block_with_hash_index();
+    assert!(block.has_hash_index());
+    let decoded_block = Block::decode(block.encode()).unwrap();
+    assert_eq!(block.offsets, decoded_block.offsets);
+    assert_eq!(block.data, decoded_block.data);
+    assert_eq!(block.buckets, decoded_block.buckets);
+    assert!(!Block::decode(generate_block().encode())
+        .unwrap()
+        .has_hash_index());
+}
+
+#[test]
+fn test_block_hash_index_full() {
+    let mut builder = BlockBuilder::new_with_hash_index(24);
+    assert!(builder.add(b"11", b"11"));
+    assert!(!builder.add(b"22", b"22"));
+    let encoded = builder.build().encode();
+    assert!(encoded.len() <= 24);
+}
+
+#[test]
+fn test_block_seek_exact_key() {
+    for block in [generate_block(), generate_block_with_hash_index()] {
+        let mut iter = BlockIterator::create_and_seek_to_first(Arc::new(block));
+        for i in 0..num_of_keys() {
+            assert!(iter.seek_to_exact_key(&key_of(i)));
+            assert_eq!(iter.key(), key_of(i));
+            assert_eq!(iter.value(), value_of(i));
+            assert!(!iter.seek_to_exact_key(&format!("key_{:03}", i * 5 + 1).into_bytes()));
+            assert!(!iter.is_valid());
+        }
+        assert!(!iter.seek_to_exact_key(b"k"));
+        // Range seeks still use binary search.
+        iter.seek_to_key(&format!("key_{:03}", 6).into_bytes());
+        assert_eq!(iter.key(), key_of(2));
+    }
+}
+
+#[test]
+fn test_block_iterator_zero_copy() {
+    let block = Arc::new(Block::decode(generate_block().encode()).unwrap());
+    let data_range = block.data.as_ptr_range();
+    let mut iter = BlockIterator::create_and_seek_to_first(block);
+    for i in 0..num_of_keys() {
+        let (key, value) = (iter.key_bytes(), iter.value_bytes());
+        assert_eq!(key, key_of(i));
+        assert_eq!(value, value_of(i));
+        assert!(data_range.contains(&key.as_ptr()));
+        assert!(data_range.contains(&value.as_ptr()));
+        assert!(data_range.contains(&iter.value().as_ptr()));
+        iter.next();
+    }
+}
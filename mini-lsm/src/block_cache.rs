@@ -0,0 +1,165 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use moka::sync::{Cache, ConcurrentCacheExt};
+
+use crate::block::Block;
+
+/// Key of a cached block: the id of the database in the cache, the SST id and the block index.
+type CacheKey = (usize, usize, usize);
+
+/// Counters of a block cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    /// Number of lookups served from the cache.
+    pub hits: u64,
+    /// Number of lookups that had to read the block.
+    pub misses: u64,
+    /// Number of blocks evicted to stay within the capacity.
+    pub evictions: u64,
+    /// Number of cached blocks.
+    pub entry_count: u64,
+    /// Total size of the cached blocks in bytes.
+    pub size: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// A block cache bounded by the total size of the cached blocks in bytes. It can be shared by
+/// multiple storage instances in one process, each of them accessing it through a [`BlockCache`].
+pub struct SharedBlockCache {
+    cache: Cache<CacheKey, Arc<Block>>,
+    capacity: u64,
+    counters: Arc<Counters>,
+    next_cache_id: AtomicUsize,
+}
+
+impl SharedBlockCache {
+    /// Create a block cache holding up to `capacity` bytes of blocks.
+    pub fn new(capacity: u64) -> Self {
+        let counters = Arc::new(Counters::default());
+        let eviction_counters = counters.clone();
+        let cache = Cache::builder()
+            .max_capacity(capacity)
+            .weigher(|_, block: &Arc<Block>| block.size().try_into().unwrap_or(u32::MAX))
+            .eviction_listener(move |_, _, cause| {
+                if cause.was_evicted() {
+                    eviction_counters.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .support_invalidation_closures()
+            .build();
+        Self {
+            cache,
+            capacity,
+            counters,
+            next_cache_id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the capacity of the cache in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Get the counters of the cache, for all databases sharing it.
+    pub fn stats(&self) -> BlockCacheStats {
+        // Apply pending evictions so that the counters are up to date.
+        self.cache.sync();
+        BlockCacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            entry_count: self.cache.entry_count(),
+            size: self.cache.weighted_size(),
+        }
+    }
+}
+
+impl fmt::Debug for SharedBlockCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedBlockCache")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+/// The block cache of one database. Blocks are keyed by `(sst id, block index)`, and the keys of
+/// different databases sharing the same [`SharedBlockCache`] never collide.
+pub struct BlockCache {
+    shared: Arc<SharedBlockCache>,
+    cache_id: usize,
+}
+
+impl BlockCache {
+    /// Create a block cache of `capacity` bytes that is not shared with other databases.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_shared(Arc::new(SharedBlockCache::new(capacity)))
+    }
+
+    /// Create a block cache backed by a shared cache.
+    pub fn with_shared(shared: Arc<SharedBlockCache>) -> Self {
+        let cache_id = shared.next_cache_id.fetch_add(1, Ordering::SeqCst);
+        Self { shared, cache_id }
+    }
+
+    /// Get the cache backing this block cache.
+    pub fn shared(&self) -> &Arc<SharedBlockCache> {
+        &self.shared
+    }
+
+    /// Get a block from the cache, or read it with `init` and insert it on a miss.
+    pub fn try_get_with(
+        &self,
+        (sst_id, block_idx): (usize, usize),
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        let counters = &self.shared.counters;
+        let mut missed = false;
+        let block = self
+            .shared
+            .cache
+            .try_get_with((self.cache_id, sst_id, block_idx), || {
+                missed = true;
+                init()
+            })
+            .map_err(|e| anyhow!("{}", e))?;
+        if missed {
+            counters.misses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            counters.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(block)
+    }
+
+    /// Check if a block is in the cache.
+    pub fn contains_key(&self, (sst_id, block_idx): &(usize, usize)) -> bool {
+        self.shared
+            .cache
+            .contains_key(&(self.cache_id, *sst_id, *block_idx))
+    }
+
+    /// Remove all blocks of this database from the cache.
+    pub fn invalidate_all(&self) {
+        let cache_id = self.cache_id;
+        self.shared
+            .cache
+            .invalidate_entries_if(move |key, _| key.0 == cache_id)
+            .expect("invalidation closures are enabled");
+    }
+
+    /// Get the counters of the backing cache.
+    pub fn stats(&self) -> BlockCacheStats {
+        self.shared.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use super::*;
+use crate::block::BlockBuilder;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+fn block_of_size(size: usize) -> Arc<Block> {
+    let mut builder = BlockBuilder::new(size * 2);
+    assert!(builder.add(b"key", &vec![b'x'; size]));
+    Arc::new(builder.build())
+}
+
+#[test]
+fn test_block_cache_counters() {
+    let cache = BlockCache::new(1 << 20);
+    let block = cache
+        .try_get_with((1, 0), || Ok(block_of_size(100)))
+        .unwrap();
+    assert_eq!(block.size(), 100 + 3 + 2 + 2 + 2);
+    cache.try_get_with((1, 0), || unreachable!()).unwrap();
+    cache
+        .try_get_with((1, 1), || Ok(block_of_size(100)))
+        .unwrap();
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.evictions, 0);
+    assert_eq!(stats.entry_count, 2);
+    assert_eq!(stats.size, 2 * block.size() as u64);
+    assert!(cache
+        .try_get_with((1, 2), || Err(anyhow!("io error")))
+        .is_err());
+}
+
+#[test]
+fn test_block_cache_weighted_by_bytes() {
+    let cache = BlockCache::new(10_000);
+    for idx in 0..100 {
+        cache
+            .try_get_with((1, idx), || Ok(block_of_size(1000)))
+            .unwrap();
+    }
+    let stats = cache.stats();
+    assert!(stats.size <= 10_000);
+    assert!(stats.entry_count < 10);
+    assert!(stats.evictions > 0);
+}
+
+#[test]
+fn test_block_cache_shared_between_databases() {
+    let shared = Arc::new(SharedBlockCache::new(1 << 20));
+    let options = LsmStorageOptions {
+        block_cache: Some(shared.clone()),
+        ..Default::default()
+    };
+    let (dir1, dir2) = (tempdir().unwrap(), tempdir().unwrap());
+    let storage1 = LsmStorage::open_with_options(&dir1, options.clone()).unwrap();
+    let storage2 = LsmStorage::open_with_options(&dir2, options).unwrap();
+    // Both databases flush an SST with the same id.
+    storage1.put(b"key", b"value1").unwrap();
+    storage1.sync().unwrap();
+    storage2.put(b"key", b"value2").unwrap();
+    storage2.sync().unwrap();
+    for _ in 0..2 {
+        assert_eq!(&storage1.get(b"key").unwrap().unwrap()[..], b"value1");
+        assert_eq!(&storage2.get(b"key").unwrap().unwrap()[..], b"value2");
+    }
+    let stats = shared.stats();
+    assert_eq!(stats.entry_count, 2);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.hits, 2);
+    assert_eq!(storage1.block_cache_stats(), stats);
+
+    storage1.block_cache.invalidate_all();
+    assert!(!storage1.block_cache.contains_key(&(1, 0)));
+    assert!(storage2.block_cache.contains_key(&(1, 0)));
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use crate::blob::StoredValue;
+use crate::event_listener::TableFileCreationReason;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{BulkLoadOptions, LsmStorage};
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator, SsTableMeta};
+
+impl LsmStorage {
+    /// Load a stream of key-value pairs in any order, without going through the memtables. If a
+    /// key occurs several times, the last pair wins. Values are stored inline, even if they are
+    /// larger than the blob threshold.
+    ///
+    /// The pairs are buffered up to `memory_limit` bytes, then sorted and spilled to a temporary
+    /// run. The runs are merged into non-overlapping SSTs, which are added to the storage like
+    /// [`LsmStorage::ingest_files`] does: as the newest data, into the bottom level if they do not
+    /// overlap existing data. Memory use is bounded by the buffer and a block per run.
+    pub fn bulk_load<K, V>(
+        &self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+        options: &BulkLoadOptions,
+    ) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut runs = Vec::new();
+        let result = self
+            .spill_runs(pairs, options, &mut runs)
+            .and_then(|_| self.merge_runs(&runs, options));
+        // The runs are orphans, removed on the next open if the process crashes before this.
+        for run in &runs {
+            self.table_cache.invalidate(run.sst_id());
+            self.env().delete(&self.path_of_sst(run.sst_id()))?;
+        }
+        let tables = result?;
+        let (Some(first), Some(last)) = (tables.first(), tables.last()) else {
+            return Ok(());
+        };
+        self.flush_overlapping_memtables(first.first_key(), last.last_key())?;
+        self.install_ingested_tables(tables, TableFileCreationReason::BulkLoad)
+    }
+
+    /// Sort the pairs into runs of at most `memory_limit` bytes, written to temporary SSTs, from
+    /// the earliest to the latest.
+    fn spill_runs<K, V>(
+        &self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+        options: &BulkLoadOptions,
+        runs: &mut Vec<Arc<SsTable>>,
+    ) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut buffer: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        let mut buffer_size = 0;
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            if key.is_empty() || value.is_empty() {
+                bail!("keys and values cannot be empty");
+            }
+            buffer_size += key.len() + value.len();
+            buffer.push((key.to_vec(), value.to_vec()));
+            if buffer_size >= options.memory_limit {
+                runs.push(self.spill_run(&mut buffer)?);
+                buffer_size = 0;
+            }
+        }
+        if !buffer.is_empty() {
+            runs.push(self.spill_run(&mut buffer)?);
+        }
+        Ok(())
+    }
+
+    /// Sort the buffer and write it to a temporary SST, keeping the last pair of each key.
+    fn spill_run(&self, buffer: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<Arc<SsTable>> {
+        // The sort is stable, so the pairs of a key stay in their input order.
+        buffer.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut builder = SsTableBuilder::new(self.options.block_size);
+        for (idx, (key, value)) in buffer.iter().enumerate() {
+            if buffer.get(idx + 1).is_some_and(|(next, _)| next == key) {
+                continue;
+            }
+            builder.add(key, value);
+        }
+        buffer.clear();
+        let id = self.next_sst_id();
+        let run = builder.build_with_env(id, None, self.env(), self.path_of_sst(id))?;
+        Ok(Arc::new(run))
+    }
+
+    /// Merge the runs into new SSTs of about `target_sst_size` bytes.
+    fn merge_runs(
+        &self,
+        runs: &[Arc<SsTable>],
+        options: &BulkLoadOptions,
+    ) -> Result<Vec<Arc<SsTableMeta>>> {
+        // The merge prefers the first iterator holding a key, so put the latest run first.
+        let mut iters = Vec::with_capacity(runs.len());
+        for run in runs.iter().rev() {
+            iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+                run.clone(),
+            )?));
+        }
+        let mut iter = MergeIterator::create(iters);
+
+        let mut tables = Vec::new();
+        let mut builder: Option<SsTableBuilder> = None;
+        let mut buf = Vec::new();
+        while iter.is_valid() {
+            let builder_inner =
+                builder.get_or_insert_with(|| self.new_sst_builder(self.options.block_size));
+            buf.clear();
+            StoredValue::Inline(iter.value()).encode(&mut buf);
+            builder_inner.add_with_value_size(iter.key(), &buf, iter.value().len());
+            iter.next()?;
+            if builder_inner.estimated_size() >= options.target_sst_size {
+                tables.push(self.build_new_sst(builder.take().unwrap())?);
+            }
+        }
+        if let Some(builder) = builder {
+            tables.push(self.build_new_sst(builder)?);
+        }
+        Ok(tables)
+    }
+
+    fn build_new_sst(&self, builder: SsTableBuilder) -> Result<Arc<SsTableMeta>> {
+        let id = self.next_sst_id();
+        Ok(self.add_new_sst(builder.build_with_env(
+            id,
+            Some(self.block_cache.clone()),
+            self.env(),
+            self.path_of_sst(id),
+        )?))
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::lsm_storage::LsmStorage;
+use crate::manifest::Manifest;
+
+impl LsmStorage {
+    /// Create a consistent copy of the storage in `dir`, which can be opened as an independent
+    /// database. The memtables are flushed first, then every SST and blob file of the current
+    /// snapshot is hard-linked into `dir`, or copied if `dir` is on another file system, and a
+    /// manifest listing them is written last. Writers are not blocked, but writes that happen
+    /// after the flush are not part of the checkpoint.
+    ///
+    /// `dir` must not exist or be empty. A checkpoint interrupted by a crash holds no manifest and
+    /// must be removed before retrying.
+    pub fn checkpoint(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        if dir == self.path() {
+            bail!("cannot checkpoint the storage into its own directory");
+        }
+        self.env().create_dir_all(dir)?;
+        if !self.env().list(dir)?.is_empty() {
+            bail!("checkpoint directory {} is not empty", dir.display());
+        }
+
+        self.sync()?;
+        // Files are only removed once they have left the snapshot, which requires the flush lock,
+        // so all files of the snapshot stay around until they are linked.
+        let _flush_lock = self.flush_lock.lock();
+        let snapshot = self.inner.read().clone();
+        let manifest = Manifest::from_snapshot(&snapshot, self.peek_next_sst_id());
+        for id in manifest.sst_ids() {
+            let from = self.path_of_sst(id);
+            self.env()
+                .link_file(&from, &dir.join(from.file_name().unwrap()))?;
+        }
+        for id in &manifest.blob_files {
+            let from = self.path_of_blob(*id);
+            self.env()
+                .link_file(&from, &dir.join(from.file_name().unwrap()))?;
+        }
+        // The manifest makes the checkpoint complete, and syncs the directory with the links.
+        manifest.write(self.env(), dir)
+    }
+}
@@ -1,11 +1,14 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
 
 use crate::{
+    blob::StoredValue,
     iterators::{merge_iterator::MergeIterator, StorageIterator},
     lsm_storage::LsmStorage,
-    table::{SsTable, SsTableBuilder, SsTableIterator},
+    rate_limiter::IoPriority,
+    table::{SsTableIterator, SsTableMeta},
 };
 
 struct CompactOptions {
@@ -18,15 +21,42 @@ impl LsmStorage {
     #[allow(dead_code)]
     fn compact(
         &self,
-        tables: Vec<Arc<SsTable>>,
+        tables: Vec<Arc<SsTableMeta>>,
         options: CompactOptions,
-    ) -> Result<Vec<Arc<SsTable>>> {
-        let mut iters = Vec::new();
-        iters.reserve(tables.len());
+    ) -> Result<Vec<Arc<SsTableMeta>>> {
+        let start = Instant::now();
+        let new_sst = self.compact_tables(&tables, &options)?;
+        self.stats.num_compactions.inc();
+        self.stats
+            .compaction_bytes_read
+            .add(tables.iter().map(|table| table.file_size()).sum());
+        self.stats
+            .compaction_bytes_written
+            .add(new_sst.iter().map(|table| table.file_size()).sum());
+        self.stats.compaction_latency.record_since(start);
+        Ok(new_sst)
+    }
+
+    /// Merge the tables into new SSTs of about `target_sst_size` bytes.
+    fn compact_tables(
+        &self,
+        tables: &[Arc<SsTableMeta>],
+        options: &CompactOptions,
+    ) -> Result<Vec<Arc<SsTableMeta>>> {
+        let mut iters = Vec::with_capacity(tables.len());
         for table in tables.iter() {
-            iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
-                table.clone(),
-            )?));
+            let table = self.open_sst(table)?;
+            let iter = match self.options.rate_limiter {
+                Some(ref rate_limiter) => {
+                    SsTableIterator::create_and_seek_to_first_with_rate_limiter(
+                        table,
+                        rate_limiter.clone(),
+                        IoPriority::Compaction,
+                    )?
+                }
+                None => SsTableIterator::create_and_seek_to_first(table)?,
+            };
+            iters.push(Box::new(iter));
         }
         let mut iter = MergeIterator::create(iters);
 
@@ -35,24 +65,27 @@ impl LsmStorage {
 
         while iter.is_valid() {
             if builder.is_none() {
-                builder = Some(SsTableBuilder::new(options.block_size));
+                builder = Some(
+                    self.new_background_sst_builder(options.block_size, IoPriority::Compaction),
+                );
             }
             let builder_inner = builder.as_mut().unwrap();
-            if options.compact_to_bottom_level {
-                if !iter.value().is_empty() {
-                    builder_inner.add(iter.key(), iter.value());
-                }
-            } else {
-                builder_inner.add(iter.key(), iter.value());
+            let value = iter.value();
+            if !value.is_empty() {
+                let value_size = StoredValue::decode(value)?.value_len();
+                builder_inner.add_with_value_size(iter.key(), value, value_size);
+            } else if !options.compact_to_bottom_level {
+                builder_inner.add(iter.key(), value);
             }
             iter.next()?;
 
             if builder_inner.estimated_size() >= options.target_sst_size {
                 let sst_id = self.next_sst_id(); // lock dropped here
                 let builder = builder.take().unwrap();
-                let sst = Arc::new(builder.build(
+                let sst = self.add_new_sst(builder.build_with_env(
                     sst_id,
                     Some(self.block_cache.clone()),
+                    self.env(),
                     self.path_of_sst(sst_id),
                 )?);
                 new_sst.push(sst);
@@ -60,9 +93,10 @@ impl LsmStorage {
         }
         if let Some(builder) = builder {
             let sst_id = self.next_sst_id(); // lock dropped here
-            let sst = Arc::new(builder.build(
+            let sst = self.add_new_sst(builder.build_with_env(
                 sst_id,
                 Some(self.block_cache.clone()),
+                self.env(),
                 self.path_of_sst(sst_id),
             )?);
             new_sst.push(sst);
@@ -70,3 +104,6 @@ impl LsmStorage {
         Ok(new_sst)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use super::*;
+use crate::lsm_storage::LsmStorageOptions;
+use crate::rate_limiter::RateLimiter;
+
+fn compact_options() -> CompactOptions {
+    CompactOptions {
+        block_size: 4096,
+        target_sst_size: 1 << 20,
+        compact_to_bottom_level: false,
+    }
+}
+
+#[test]
+fn test_compaction_rate_limit() {
+    let dir = tempdir().unwrap();
+    let rate_limiter = Arc::new(RateLimiter::new(0));
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            rate_limiter: Some(rate_limiter.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for version in 0..2 {
+        for idx in 0..1000 {
+            storage
+                .put(format!("key_{:04}", idx).as_bytes(), &[version; 100])
+                .unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    let tables = storage.inner.read().l0_sstables.clone();
+    let flushed: u64 = tables.iter().map(|table| table.file_size()).sum();
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), flushed);
+
+    let new_sst = storage.compact(tables.clone(), compact_options()).unwrap();
+    let written: u64 = new_sst.iter().map(|table| table.file_size()).sum();
+    // All data blocks are read from the files, including the first block of each table.
+    let data_size: u64 = tables
+        .iter()
+        .map(|table| storage.open_sst(table).unwrap().data_size().unwrap())
+        .sum();
+    let read = rate_limiter.total_bytes(IoPriority::Compaction) - written;
+    assert_eq!(read, data_size);
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), flushed);
+}
+
+#[test]
+fn test_raw_value_size_of_separated_values() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            blob_threshold: Some(64),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..100 {
+        let value = vec![b'v'; if idx % 2 == 0 { 10 } else { 1000 }];
+        storage
+            .put(format!("key_{:04}", idx).as_bytes(), &value)
+            .unwrap();
+    }
+    storage.delete(b"key_0000").unwrap();
+    storage.sync().unwrap();
+    // The deleted key only holds its tombstone, and blob pointers count as the values they point to.
+    let raw_value_size = 10 * 49 + 1000 * 50;
+
+    let tables = storage.inner.read().l0_sstables.clone();
+    let properties = storage.open_sst(&tables[0]).unwrap().properties().clone();
+    assert!(!storage.inner.read().blob_files.is_empty());
+    assert_eq!(properties.num_entries, 100);
+    assert_eq!(properties.num_tombstones, 1);
+    assert_eq!(properties.raw_value_size, raw_value_size);
+
+    let new_sst = storage.compact(tables, compact_options()).unwrap();
+    let properties = storage.open_sst(&new_sst[0]).unwrap().properties().clone();
+    assert_eq!(properties.num_entries, 100);
+    assert_eq!(properties.raw_value_size, raw_value_size);
+}
@@ -0,0 +1,334 @@
+mod fault_injection;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+pub use fault_injection::FaultInjectionEnv;
+
+/// A file opened for reading.
+pub trait RandomAccessFile: Send + Sync {
+    /// Read `len` bytes starting at `offset`.
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes>;
+
+    /// Size of the file in bytes.
+    fn size(&self) -> u64;
+
+    /// Check if reads are served from memory without any I/O, e.g. for memory-mapped files.
+    fn is_in_memory(&self) -> bool {
+        false
+    }
+}
+
+/// A file opened for writing. Data is appended to the end of the file.
+pub trait WritableFile: Send {
+    /// Append data to the file.
+    fn write(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Make the data written so far durable.
+    fn sync(&mut self) -> Result<()>;
+}
+
+/// The file system the storage runs on. All file access of the storage goes through this trait.
+pub trait Env: Send + Sync + fmt::Debug {
+    /// Create a file for writing, truncating it if it exists.
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>>;
+
+    /// Open an existing file for reading.
+    fn open(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>>;
+
+    /// Open an existing file for reading through a memory mapping, if the file is at most
+    /// `max_mmap_size` bytes. Environments without memory mappings fall back to [`Env::open`].
+    fn open_mmap(&self, path: &Path, max_mmap_size: u64) -> Result<Box<dyn RandomAccessFile>> {
+        let _ = max_mmap_size;
+        self.open(path)
+    }
+
+    /// Atomically rename a file, replacing the target if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Delete a file. Files that are open for reading stay readable until they are closed.
+    fn delete(&self, path: &Path) -> Result<()>;
+
+    /// Make the content of an existing file available under another path, which must not exist.
+    /// The file must not be written to afterwards. File systems that support it create a hard
+    /// link, the default copies the file.
+    fn link_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let file = self.open(from)?;
+        if self.open(to).is_ok() {
+            bail!("{} already exists", to.display());
+        }
+        let mut copy = self.create(to)?;
+        copy.write(&file.read_at(0, file.size())?)?;
+        copy.sync()
+    }
+
+    /// List the paths of the files in a directory.
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Create a directory and all of its parents if they do not exist.
+    fn create_dir_all(&self, dir: &Path) -> Result<()>;
+
+    /// Make the creations, renames and deletions of files in a directory durable.
+    fn sync_dir(&self, dir: &Path) -> Result<()>;
+}
+
+/// Write a whole file and sync it.
+pub fn write_file(env: &dyn Env, path: &Path, data: &[u8]) -> Result<()> {
+    let mut file = env.create(path)?;
+    file.write(data)?;
+    file.sync()
+}
+
+/// The file system of the operating system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PosixEnv;
+
+struct PosixFile {
+    file: File,
+    size: u64,
+}
+
+impl RandomAccessFile for PosixFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        use std::os::unix::fs::FileExt;
+        let mut data = vec![0; len as usize];
+        self.file.read_exact_at(&mut data[..], offset)?;
+        Ok(data.into())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A file whose content is held in memory, either a memory mapping or a snapshot of a file in a
+/// [`MemEnv`]. Reads return slices of the content instead of copying the data.
+struct BytesFile {
+    data: Bytes,
+}
+
+impl RandomAccessFile for BytesFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        let end = offset + len;
+        if end > self.data.len() as u64 {
+            bail!(
+                "read {}..{} out of a file of {} bytes",
+                offset,
+                end,
+                self.data.len()
+            );
+        }
+        Ok(self.data.slice(offset as usize..end as usize))
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn is_in_memory(&self) -> bool {
+        true
+    }
+}
+
+struct PosixWritableFile(File);
+
+impl WritableFile for PosixWritableFile {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.0.write_all(data)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.0.sync_all()?;
+        Ok(())
+    }
+}
+
+impl Env for PosixEnv {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        Ok(Box::new(PosixWritableFile(File::create(path)?)))
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        let file = File::options().read(true).write(false).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Box::new(PosixFile { file, size }))
+    }
+
+    fn open_mmap(&self, path: &Path, max_mmap_size: u64) -> Result<Box<dyn RandomAccessFile>> {
+        let file = File::options().read(true).write(false).open(path)?;
+        let size = file.metadata()?.len();
+        if size == 0 || size > max_mmap_size {
+            return Ok(Box::new(PosixFile { file, size }));
+        }
+        // SAFETY: files are never modified once written, and unlinking a file keeps existing
+        // mappings valid.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Box::new(BytesFile {
+            data: Bytes::from_owner(mmap),
+        }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        std::fs::rename(from, to)?;
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> Result<()> {
+        match std::fs::hard_link(from, to) {
+            Ok(()) => Ok(()),
+            // Hard links cannot cross file systems, copy the file instead.
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                std::fs::copy(from, to)?;
+                File::open(to)?.sync_all()?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        Ok(())
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+type MemFiles = Arc<Mutex<HashMap<PathBuf, Arc<Mutex<Vec<u8>>>>>>;
+
+/// A file system held in memory, for tests and ephemeral storage. Directories are implicit: a
+/// directory contains the files whose parent is that directory.
+#[derive(Clone, Default)]
+pub struct MemEnv {
+    files: MemFiles,
+}
+
+impl MemEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, path: &Path) -> Result<Arc<Mutex<Vec<u8>>>> {
+        self.files
+            .lock()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow!("file {} not found", path.display()))
+    }
+}
+
+impl fmt::Debug for MemEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemEnv")
+            .field("num_files", &self.files.lock().len())
+            .finish()
+    }
+}
+
+struct MemWritableFile {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl WritableFile for MemWritableFile {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.data.lock().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Env for MemEnv {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        self.files.lock().insert(path.to_path_buf(), data.clone());
+        Ok(Box::new(MemWritableFile { data }))
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        // Readers see the content at the time the file is opened.
+        let data = Bytes::copy_from_slice(&self.get(path)?.lock());
+        Ok(Box::new(BytesFile { data }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut files = self.files.lock();
+        let data = files
+            .remove(from)
+            .ok_or_else(|| anyhow!("file {} not found", from.display()))?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.files
+            .lock()
+            .remove(path)
+            .ok_or_else(|| anyhow!("file {} not found", path.display()))?;
+        Ok(())
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let data = self.get(from)?;
+        let mut files = self.files.lock();
+        if files.contains_key(to) {
+            bail!("{} already exists", to.display());
+        }
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<_> = self
+            .files
+            .lock()
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn create_dir_all(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use super::{BytesFile, Env, RandomAccessFile, WritableFile};
+
+/// The content of a file, which may be linked under several names across the live and the durable
+/// view of the file system.
+#[derive(Default)]
+struct Inode {
+    data: Vec<u8>,
+    /// Length of the prefix of `data` that has been synced.
+    synced_len: usize,
+}
+
+#[derive(Default)]
+struct State {
+    inodes: HashMap<u64, Inode>,
+    next_inode: u64,
+    /// The files as seen by the running process.
+    names: HashMap<PathBuf, u64>,
+    /// The files as of the last directory syncs, which survive a power loss.
+    durable_names: HashMap<PathBuf, u64>,
+    /// Number of I/O calls that still succeed before all calls fail, `None` to never fail.
+    remaining_ops: Option<usize>,
+}
+
+impl State {
+    /// Count an I/O call, and fail it if the injected failure has been reached.
+    fn check_op(&mut self) -> Result<()> {
+        match self.remaining_ops.as_mut() {
+            Some(0) => bail!("injected I/O error"),
+            Some(remaining) => {
+                *remaining -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn inode_of(&self, path: &Path) -> Result<u64> {
+        self.names
+            .get(path)
+            .copied()
+            .ok_or_else(|| anyhow!("file {} not found", path.display()))
+    }
+}
+
+/// A file system in memory that can simulate failures and crashes, for testing crash consistency.
+///
+/// Like a real file system, written data only survives a power loss once the file is synced, and
+/// file creations, renames and deletions only once their directory is synced. On top of that, I/O
+/// calls can be made to fail, and a power loss can tear the unsynced data of files at an
+/// arbitrary byte offset instead of dropping it.
+#[derive(Clone, Default)]
+pub struct FaultInjectionEnv {
+    state: Arc<Mutex<State>>,
+}
+
+impl FaultInjectionEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the next `ops` I/O calls succeed, and fail all calls after them.
+    pub fn fail_after(&self, ops: usize) {
+        self.state.lock().remaining_ops = Some(ops);
+    }
+
+    /// Stop failing I/O calls.
+    pub fn clear_failures(&self) {
+        self.state.lock().remaining_ops = None;
+    }
+
+    /// Simulate a power loss: all unsynced data and directory changes are lost. Files opened
+    /// before keep reading the old content, the storage must be reopened afterwards.
+    pub fn simulate_power_loss(&self) {
+        self.power_loss(|_, _| 0);
+    }
+
+    /// Simulate a power loss where the unsynced data of each file is torn at a random byte
+    /// offset: a random prefix of it survives, the rest is lost.
+    pub fn simulate_power_loss_with_torn_writes(&self, seed: u64) {
+        let mut rng = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+        self.power_loss(|_, unsynced| {
+            // xorshift64
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            rng as usize % (unsynced + 1)
+        });
+    }
+
+    /// Drop unsynced changes, keeping `kept(inode, unsynced_len)` bytes of unsynced data.
+    fn power_loss(&self, mut kept: impl FnMut(u64, usize) -> usize) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        state.names = state.durable_names.clone();
+        let mut live: Vec<_> = state.names.values().copied().collect();
+        live.sort_unstable();
+        live.dedup();
+        state.inodes.retain(|id, _| live.binary_search(id).is_ok());
+        let mut inodes: Vec<_> = state.inodes.iter_mut().collect();
+        // Visit the files in a fixed order so that torn writes are reproducible.
+        inodes.sort_unstable_by_key(|(id, _)| **id);
+        for (id, inode) in inodes {
+            let unsynced = inode.data.len() - inode.synced_len;
+            let len = inode.synced_len + kept(*id, unsynced);
+            inode.data.truncate(len);
+            inode.synced_len = len;
+        }
+    }
+}
+
+impl fmt::Debug for FaultInjectionEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("FaultInjectionEnv")
+            .field("num_files", &state.names.len())
+            .field("remaining_ops", &state.remaining_ops)
+            .finish()
+    }
+}
+
+struct FaultInjectionWritableFile {
+    state: Arc<Mutex<State>>,
+    inode: u64,
+}
+
+impl WritableFile for FaultInjectionWritableFile {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        if let Some(inode) = state.inodes.get_mut(&self.inode) {
+            inode.data.extend_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        if let Some(inode) = state.inodes.get_mut(&self.inode) {
+            inode.synced_len = inode.data.len();
+        }
+        Ok(())
+    }
+}
+
+impl Env for FaultInjectionEnv {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let inode = state.next_inode;
+        state.next_inode += 1;
+        state.inodes.insert(inode, Inode::default());
+        state.names.insert(path.to_path_buf(), inode);
+        Ok(Box::new(FaultInjectionWritableFile {
+            state: self.state.clone(),
+            inode,
+        }))
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let inode = state.inode_of(path)?;
+        let data = Bytes::copy_from_slice(&state.inodes[&inode].data);
+        Ok(Box::new(BytesFile { data }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let inode = state.inode_of(from)?;
+        state.names.remove(from);
+        state.names.insert(to.to_path_buf(), inode);
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        state.inode_of(path)?;
+        state.names.remove(path);
+        Ok(())
+    }
+
+    fn link_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let inode = state.inode_of(from)?;
+        if state.names.contains_key(to) {
+            bail!("{} already exists", to.display());
+        }
+        state.names.insert(to.to_path_buf(), inode);
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let mut paths: Vec<_> = state
+            .names
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn create_dir_all(&self, _dir: &Path) -> Result<()> {
+        self.state.lock().check_op()
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_op()?;
+        let state = &mut *state;
+        state
+            .durable_names
+            .retain(|path, _| path.parent() != Some(dir));
+        for (path, inode) in &state.names {
+            if path.parent() == Some(dir) {
+                state.durable_names.insert(path.clone(), *inode);
+            }
+        }
+        Ok(())
+    }
+}
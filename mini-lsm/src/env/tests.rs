@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use super::*;
+
+fn test_env(env: &dyn Env, dir: &Path) {
+    env.create_dir_all(dir).unwrap();
+    let path = dir.join("1.sst");
+    let mut file = env.create(&path).unwrap();
+    file.write(b"hello, ").unwrap();
+    file.write(b"world").unwrap();
+    file.sync().unwrap();
+    drop(file);
+
+    let file = env.open(&path).unwrap();
+    assert_eq!(file.size(), 12);
+    assert_eq!(&file.read_at(7, 5).unwrap()[..], b"world");
+    assert!(file.read_at(7, 10).is_err());
+
+    write_file(env, &dir.join("2.sst"), b"another").unwrap();
+    assert_eq!(
+        env.list(dir).unwrap(),
+        vec![path.clone(), dir.join("2.sst")]
+    );
+
+    // Renaming replaces the target.
+    env.rename(&dir.join("2.sst"), &path).unwrap();
+    assert_eq!(env.list(dir).unwrap(), vec![path.clone()]);
+    assert_eq!(
+        &env.open(&path).unwrap().read_at(0, 7).unwrap()[..],
+        b"another"
+    );
+    // A file opened before the rename keeps its content.
+    assert_eq!(&file.read_at(0, 5).unwrap()[..], b"hello");
+
+    // A linked file has the same content, and outlives the original.
+    let linked = dir.join("3.sst");
+    env.link_file(&path, &linked).unwrap();
+    assert_eq!(
+        &env.open(&linked).unwrap().read_at(0, 7).unwrap()[..],
+        b"another"
+    );
+    env.delete(&linked).unwrap();
+    assert!(env.open(&linked).is_err());
+    env.link_file(&path, &linked).unwrap();
+    env.delete(&path).unwrap();
+    assert_eq!(
+        &env.open(&linked).unwrap().read_at(0, 7).unwrap()[..],
+        b"another"
+    );
+    env.link_file(&linked, &path).unwrap();
+    // The target must not exist, and the source must.
+    write_file(env, &dir.join("other"), b"other").unwrap();
+    assert!(env.link_file(&dir.join("other"), &path).is_err());
+    assert!(env
+        .link_file(&dir.join("missing"), &dir.join("new"))
+        .is_err());
+    assert!(env.open(&dir.join("new")).is_err());
+    assert_eq!(
+        &env.open(&path).unwrap().read_at(0, 7).unwrap()[..],
+        b"another"
+    );
+    env.delete(&dir.join("other")).unwrap();
+    env.delete(&linked).unwrap();
+
+    env.delete(&path).unwrap();
+    assert!(env.list(dir).unwrap().is_empty());
+    assert!(env.open(&path).is_err());
+    assert!(env.delete(&path).is_err());
+    assert_eq!(&file.read_at(0, 5).unwrap()[..], b"hello");
+}
+
+#[test]
+fn test_posix_env() {
+    let dir = tempdir().unwrap();
+    test_env(&PosixEnv, &dir.path().join("db"));
+}
+
+#[test]
+fn test_mem_env() {
+    let env = MemEnv::new();
+    test_env(&env, Path::new("/db"));
+    // Files in subdirectories are not listed.
+    write_file(&env, Path::new("/db/sub/1.sst"), b"data").unwrap();
+    assert!(env.list(Path::new("/db")).unwrap().is_empty());
+    assert_eq!(
+        env.list(Path::new("/db/sub")).unwrap(),
+        vec![Path::new("/db/sub/1.sst").to_path_buf()]
+    );
+}
+
+#[test]
+fn test_posix_env_mmap() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    write_file(&PosixEnv, &path, b"hello, world").unwrap();
+    let file = PosixEnv.open_mmap(&path, 12).unwrap();
+    assert!(file.is_in_memory());
+    assert_eq!(&file.read_at(7, 5).unwrap()[..], b"world");
+    let file = PosixEnv.open_mmap(&path, 11).unwrap();
+    assert!(!file.is_in_memory());
+    assert_eq!(&file.read_at(7, 5).unwrap()[..], b"world");
+}
+
+#[test]
+fn test_fault_injection_env() {
+    test_env(&FaultInjectionEnv::new(), Path::new("/db"));
+}
+
+#[test]
+fn test_fault_injection_power_loss() {
+    let env = FaultInjectionEnv::new();
+    let dir = Path::new("/db");
+    write_file(&env, &dir.join("synced"), b"synced").unwrap();
+    env.sync_dir(dir).unwrap();
+    let mut file = env.create(&dir.join("partial")).unwrap();
+    file.write(b"hello").unwrap();
+    file.sync().unwrap();
+    file.write(b", world").unwrap();
+    env.sync_dir(dir).unwrap();
+    // Not durable: the directory is not synced after these.
+    write_file(&env, &dir.join("unlinked"), b"unlinked").unwrap();
+    env.rename(&dir.join("synced"), &dir.join("renamed"))
+        .unwrap();
+
+    env.simulate_power_loss();
+    assert_eq!(
+        env.list(dir).unwrap(),
+        vec![dir.join("partial"), dir.join("synced")]
+    );
+    let file = env.open(&dir.join("partial")).unwrap();
+    assert_eq!(&file.read_at(0, file.size()).unwrap()[..], b"hello");
+    let file = env.open(&dir.join("synced")).unwrap();
+    assert_eq!(&file.read_at(0, file.size()).unwrap()[..], b"synced");
+}
+
+#[test]
+fn test_fault_injection_torn_writes() {
+    let mut sizes = Vec::new();
+    for seed in 0..20 {
+        let env = FaultInjectionEnv::new();
+        let path = Path::new("/db/1.sst");
+        let mut file = env.create(path).unwrap();
+        file.write(b"hello").unwrap();
+        file.sync().unwrap();
+        file.write(b", world").unwrap();
+        env.sync_dir(Path::new("/db")).unwrap();
+        env.simulate_power_loss_with_torn_writes(seed);
+        let file = env.open(path).unwrap();
+        let data = file.read_at(0, file.size()).unwrap();
+        assert!(b"hello, world".starts_with(&data));
+        assert!(data.len() >= 5);
+        sizes.push(data.len());
+    }
+    sizes.sort_unstable();
+    sizes.dedup();
+    assert!(sizes.len() > 1);
+}
+
+#[test]
+fn test_fault_injection_failures() {
+    let env = FaultInjectionEnv::new();
+    let path = Path::new("/db/1.sst");
+    env.fail_after(2);
+    let mut file = env.create(path).unwrap();
+    file.write(b"hello").unwrap();
+    assert!(file.sync().is_err());
+    assert!(env.open(path).is_err());
+    env.clear_failures();
+    file.sync().unwrap();
+    assert_eq!(env.open(path).unwrap().size(), 5);
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Callbacks for the background work of the storage and the lifecycle of its files. Listeners are
+/// registered in [`LsmStorageOptions::listeners`](crate::lsm_storage::LsmStorageOptions) and
+/// called synchronously on the thread doing the work, so they should return quickly. All methods
+/// do nothing by default.
+pub trait EventListener: Send + Sync + fmt::Debug {
+    /// Called after a memtable has been flushed to a new L0 table and added to the manifest.
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    /// Called after an SST has been written to the storage directory.
+    fn on_table_file_created(&self, _info: &TableFileCreationInfo) {}
+
+    /// Called after an SST has been removed from the storage directory.
+    fn on_table_file_deleted(&self, _info: &TableFileDeletionInfo) {}
+
+    /// Called after a blob file has been removed from the storage directory, by the garbage
+    /// collection or as an orphan on open.
+    fn on_blob_file_deleted(&self, _info: &BlobFileDeletionInfo) {}
+
+    /// Called when a flush fails, before the error is returned.
+    fn on_background_error(&self, _reason: BackgroundErrorReason, _error: &anyhow::Error) {}
+}
+
+/// A memtable flushed to L0.
+#[derive(Debug, Clone)]
+pub struct FlushJobInfo {
+    /// Id of the new L0 table.
+    pub sst_id: usize,
+    /// Id of the blob file holding the separated values, if any.
+    pub blob_file_id: Option<usize>,
+    /// Number of entries flushed, including tombstones.
+    pub num_entries: u64,
+    /// Size of the new table and blob file in bytes.
+    pub bytes_written: u64,
+    /// Time taken by the flush.
+    pub duration: Duration,
+}
+
+/// Why an SST was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFileCreationReason {
+    Flush,
+    Ingest,
+    BulkLoad,
+}
+
+/// An SST written to the storage directory.
+#[derive(Debug, Clone)]
+pub struct TableFileCreationInfo {
+    pub sst_id: usize,
+    pub path: PathBuf,
+    /// Level the table is added to, 0 for L0.
+    pub level: usize,
+    pub file_size: u64,
+    pub num_entries: u64,
+    pub reason: TableFileCreationReason,
+}
+
+/// An SST removed from the storage directory.
+#[derive(Debug, Clone)]
+pub struct TableFileDeletionInfo {
+    pub sst_id: usize,
+    pub path: PathBuf,
+}
+
+/// A blob file removed from the storage directory.
+#[derive(Debug, Clone)]
+pub struct BlobFileDeletionInfo {
+    pub blob_file_id: usize,
+    pub path: PathBuf,
+}
+
+/// The work that failed in [`EventListener::on_background_error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundErrorReason {
+    Flush,
+}
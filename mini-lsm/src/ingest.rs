@@ -0,0 +1,152 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+
+use crate::event_listener::TableFileCreationReason;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageInner, NUM_LEVELS};
+use crate::table::{SsTable, SsTableMeta, SstFileReader};
+
+/// Get the level to ingest a table with the given key range into: the deepest level such that
+/// neither that level nor any level above it overlaps the range, so that the ingested entries are
+/// newer than everything they overlap. Level 0 if L0 overlaps the range.
+fn ingest_level(snapshot: &LsmStorageInner, first_key: &[u8], last_key: &[u8]) -> usize {
+    let overlaps = |tables: &[Arc<SsTableMeta>]| {
+        tables
+            .iter()
+            .any(|table| table.range_overlap(Bound::Included(first_key), Bound::Included(last_key)))
+    };
+    if overlaps(&snapshot.l0_sstables) {
+        return 0;
+    }
+    (0..NUM_LEVELS)
+        .position(|idx| {
+            snapshot
+                .levels
+                .get(idx)
+                .is_some_and(|level| overlaps(level))
+        })
+        .unwrap_or(NUM_LEVELS)
+}
+
+impl LsmStorage {
+    /// Add SSTs written by [`SstFileWriter`](crate::table::SstFileWriter) to the storage. The
+    /// files are verified with [`SstFileReader::verify`] and must not overlap each other. They are
+    /// hard-linked into the storage under new ids, or copied if they are on another file system.
+    /// The files are left in place and must not be modified afterwards.
+    ///
+    /// Ingested entries are newer than all existing data: the memtables are flushed first if they
+    /// overlap the files, and each file is placed at the deepest level where it overlaps no
+    /// existing data above, or in L0 if it overlaps L0. All files are added at once, so readers
+    /// either see all of them or none.
+    pub fn ingest_files(&self, paths: &[impl AsRef<Path>]) -> Result<()> {
+        let mut tables = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref();
+            let properties = SstFileReader::open_with_env(self.env(), path)
+                .and_then(|reader| {
+                    reader.verify()?;
+                    Ok(reader.properties().clone())
+                })
+                .with_context(|| format!("invalid SST {}", path.display()))?;
+            tables.push((path, properties));
+        }
+        tables.sort_by(|(_, a), (_, b)| a.first_key.cmp(&b.first_key));
+        for pair in tables.windows(2) {
+            if pair[0].1.last_key >= pair[1].1.first_key {
+                bail!(
+                    "SSTs {} and {} overlap",
+                    pair[0].0.display(),
+                    pair[1].0.display()
+                );
+            }
+        }
+        let Some(((_, first), (_, last))) = tables.first().zip(tables.last()) else {
+            return Ok(());
+        };
+
+        self.flush_overlapping_memtables(&first.first_key, &last.last_key)?;
+        let mut metas = Vec::with_capacity(tables.len());
+        for (path, _) in &tables {
+            let id = self.next_sst_id();
+            self.env().link_file(path, &self.path_of_sst(id))?;
+            metas.push(self.add_new_sst(SsTable::open(
+                id,
+                Some(self.block_cache.clone()),
+                self.open_sst_file(id)?,
+            )?));
+        }
+        self.install_ingested_tables(metas, TableFileCreationReason::Ingest)
+    }
+
+    /// Flush the memtables if they hold keys in `[first_key, last_key]`, as they would hide the
+    /// entries of tables ingested into that range otherwise.
+    pub(crate) fn flush_overlapping_memtables(
+        &self,
+        first_key: &[u8],
+        last_key: &[u8],
+    ) -> Result<()> {
+        let memtables_overlap = {
+            let snapshot = self.inner.read();
+            std::iter::once(&snapshot.memtable)
+                .chain(snapshot.imm_memtables.iter())
+                .any(|memtable| {
+                    memtable
+                        .scan(Bound::Included(first_key), Bound::Included(last_key))
+                        .is_valid()
+                })
+        };
+        if memtables_overlap {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Atomically add new tables, which must not overlap each other, to the storage as the newest
+    /// data. Each table is placed at the deepest level where it overlaps no existing data above,
+    /// or in L0 if it overlaps L0.
+    pub(crate) fn install_ingested_tables(
+        &self,
+        tables: Vec<Arc<SsTableMeta>>,
+        reason: TableFileCreationReason,
+    ) -> Result<()> {
+        let _flush_lock = self.flush_lock.lock();
+        let mut snapshot = self.inner.read().as_ref().clone();
+        let mut installed = Vec::with_capacity(tables.len());
+        for meta in tables {
+            let level = ingest_level(&snapshot, meta.first_key(), meta.last_key());
+            installed.push((level, meta.clone()));
+            match level {
+                0 => snapshot.l0_sstables.push(meta),
+                level => {
+                    if snapshot.levels.len() < level {
+                        snapshot.levels.resize(level, Vec::new());
+                    }
+                    let level = &mut snapshot.levels[level - 1];
+                    let idx = level.partition_point(|other| other.first_key() < meta.first_key());
+                    level.insert(idx, meta);
+                }
+            }
+        }
+        // The files are part of the storage once they are in the manifest. If anything fails
+        // before, the new files are orphans and removed on the next open.
+        self.write_manifest(&snapshot)?;
+        {
+            // Invalidate the row cache under the lock installing the snapshot, so that no result
+            // cached from the old snapshot is returned once the new one is visible.
+            let mut guard = self.inner.write();
+            *guard = Arc::new(snapshot);
+            if let Some(ref row_cache) = self.row_cache {
+                row_cache.invalidate_all();
+            }
+        }
+        for (level, meta) in installed {
+            self.stats.bytes_ingested.add(meta.file_size());
+            self.stats.level_bytes_written[level].add(meta.file_size());
+            self.notify_table_file_created(&meta, level, reason);
+        }
+        Ok(())
+    }
+}
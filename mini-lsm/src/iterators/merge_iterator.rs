@@ -16,8 +16,8 @@ impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
 
 impl<I: StorageIterator> Eq for HeapWrapper<I> {}
 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
-    #[allow(clippy::non_canonical_partial_ord_impl)]
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
         match self.1.key().cmp(other.1.key()) {
             cmp::Ordering::Greater => Some(cmp::Ordering::Greater),
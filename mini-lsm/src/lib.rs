@@ -1,10 +1,25 @@
+pub mod backup;
+pub mod blob;
+mod blob_gc;
 pub mod block;
+pub mod block_cache;
+mod bulk_load;
+mod checkpoint;
 mod compact;
+pub mod env;
+pub mod event_listener;
+mod ingest;
 pub mod iterators;
 pub mod lsm_iterator;
 pub mod lsm_storage;
+pub mod manifest;
 pub mod mem_table;
+pub mod prefix_extractor;
+pub mod rate_limiter;
+pub mod row_cache;
+pub mod statistics;
 pub mod table;
+pub mod table_cache;
 
 #[cfg(test)]
 mod tests;
@@ -3,14 +3,17 @@ use std::ops::Bound;
 use anyhow::Result;
 use bytes::Bytes;
 
+use crate::blob::BlobResolveIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::mem_table::MemTableIterator;
 use crate::table::SsTableIterator;
 
-type LsmIteratorInner =
-    TwoMergeIterator<MergeIterator<MemTableIterator>, MergeIterator<SsTableIterator>>;
+type LsmIteratorInner = TwoMergeIterator<
+    MergeIterator<MemTableIterator>,
+    BlobResolveIterator<MergeIterator<SsTableIterator>>,
+>;
 
 pub struct LsmIterator {
     iter: LsmIteratorInner,
@@ -21,25 +24,32 @@ pub struct LsmIterator {
 impl LsmIterator {
     pub(crate) fn new(iter: LsmIteratorInner, end_bound: Bound<Bytes>) -> Result<Self> {
         let mut iter = Self {
-            is_valid: iter.is_valid(),
+            is_valid: false,
             iter,
             end_bound,
         };
+        iter.check_end_bound();
         iter.move_to_non_delete()?;
         Ok(iter)
     }
 
-    fn next_inner(&mut self) -> Result<()> {
-        self.iter.next()?;
+    /// Update the validity after the inner iterator moves. The inner iterators only start at the
+    /// lower bound, the upper bound is checked here.
+    fn check_end_bound(&mut self) {
         if !self.iter.is_valid() {
             self.is_valid = false;
-            return Ok(());
-        }
-        match self.end_bound.as_ref() {
-            Bound::Unbounded => {}
-            Bound::Included(key) => self.is_valid = self.iter.key() <= key.as_ref(),
-            Bound::Excluded(key) => self.is_valid = self.iter.key() < key.as_ref(),
+            return;
         }
+        self.is_valid = match self.end_bound.as_ref() {
+            Bound::Unbounded => true,
+            Bound::Included(key) => self.iter.key() <= key.as_ref(),
+            Bound::Excluded(key) => self.iter.key() < key.as_ref(),
+        };
+    }
+
+    fn next_inner(&mut self) -> Result<()> {
+        self.iter.next()?;
+        self.check_end_bound();
         Ok(())
     }
 
@@ -1,33 +1,51 @@
+use std::collections::HashMap;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
 use bytes::Bytes;
 use parking_lot::{Mutex, RwLock};
 
-use crate::block::Block;
+use crate::blob::{
+    resolve_value, BlobFile, BlobFileBuilder, BlobFiles, BlobResolveIterator, StoredValue,
+};
+use crate::block_cache::{BlockCache, BlockCacheStats, SharedBlockCache};
+use crate::env::{Env, PosixEnv};
+use crate::event_listener::{
+    BackgroundErrorReason, EventListener, FlushJobInfo, TableFileCreationInfo,
+    TableFileCreationReason,
+};
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::lsm_iterator::{FusedIterator, LsmIterator};
 use crate::mem_table::{map_bound, MemTable};
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::prefix_extractor::{prefix_upper_bound, PrefixExtractor};
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::row_cache::RowCache;
+use crate::statistics::{LevelStats, Statistics, StorageStats};
+use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator, SsTableMeta};
+use crate::table_cache::TableCache;
 
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+/// Number of levels below L0.
+pub const NUM_LEVELS: usize = 6;
 
 #[derive(Clone)]
 pub struct LsmStorageInner {
     /// The current memtable.
-    memtable: Arc<MemTable>,
+    pub(crate) memtable: Arc<MemTable>,
     /// Immutable memTables, from earliest to latest.
-    imm_memtables: Vec<Arc<MemTable>>,
-    /// L0 SsTables, from earliest to latest.
-    l0_sstables: Vec<Arc<SsTable>>,
-    /// L1 - L6 SsTables, sorted by key range.
-    #[allow(dead_code)]
-    levels: Vec<Vec<Arc<SsTable>>>,
+    pub(crate) imm_memtables: Vec<Arc<MemTable>>,
+    /// L0 SsTables, from earliest to latest. The tables are opened through the table cache.
+    pub(crate) l0_sstables: Vec<Arc<SsTableMeta>>,
+    /// L1 - L6 SsTables, sorted by key range. Levels below the deepest non-empty level may be
+    /// missing.
+    pub(crate) levels: Vec<Vec<Arc<SsTableMeta>>>,
+    /// Blob files holding separated values, keyed by file id.
+    pub(crate) blob_files: Arc<BlobFiles>,
 }
 
 impl LsmStorageInner {
@@ -37,6 +55,133 @@ impl LsmStorageInner {
             imm_memtables: vec![],
             l0_sstables: vec![],
             levels: vec![],
+            blob_files: Arc::new(HashMap::new()),
+        }
+    }
+}
+
+/// Options for opening the storage.
+#[derive(Debug, Clone)]
+pub struct LsmStorageOptions {
+    /// Target size of the data blocks in an SST.
+    pub block_size: usize,
+    /// Values of at least this many bytes are moved to blob files when a memtable is flushed, and
+    /// the SST only stores a pointer to them. `None` keeps all values inline.
+    pub blob_threshold: Option<usize>,
+    /// Target size of the index partitions in an SST. `None` writes a flat index, which is fully
+    /// loaded into memory when the SST is opened.
+    pub index_partition_size: Option<usize>,
+    /// Append a hash index to the data blocks, so that point lookups do not need a binary search
+    /// within a block.
+    pub block_hash_index: bool,
+    /// Capacity of the block cache in bytes. Ignored if `block_cache` is set.
+    pub block_cache_capacity: u64,
+    /// A block cache shared with other storage instances in the process. If not set, the storage
+    /// creates its own cache of `block_cache_capacity` bytes.
+    pub block_cache: Option<Arc<SharedBlockCache>>,
+    /// Maximum number of SSTs kept open at the same time. Tables are opened on demand and the
+    /// least recently used ones are closed. `None` keeps all tables open.
+    pub max_open_files: Option<usize>,
+    /// Capacity of the row cache in bytes. The row cache keeps the results of recent `get`s, so
+    /// that reads of hot keys skip the memtables and SSTs. `None` disables the row cache.
+    pub row_cache_capacity: Option<u64>,
+    /// Read SSTs through memory mappings instead of `pread`, so that reading a block neither
+    /// copies nor allocates.
+    pub use_mmap: bool,
+    /// SSTs larger than this many bytes are read with `pread` even if `use_mmap` is set.
+    pub mmap_max_file_size: u64,
+    /// The file system the storage runs on.
+    pub env: Arc<dyn Env>,
+    /// Extracts the key prefixes of the prefix bloom filters written to the SSTs. `scan_prefix`
+    /// skips the SSTs whose filter does not hold the prefix. `None` writes no filters.
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    /// Bits per distinct prefix in the prefix bloom filters.
+    pub prefix_bloom_bits_per_key: usize,
+    /// Listeners called on flushes, and the creation and deletion of SSTs and blob files.
+    pub listeners: Vec<Arc<dyn EventListener>>,
+    /// Limits the rate at which flushes write SSTs, and compactions read and write them. Keep a
+    /// reference to change the rate at runtime, or share it with other storage instances to
+    /// bound their total background I/O. `None` leaves the I/O unlimited.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Default for LsmStorageOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 4096,
+            blob_threshold: None,
+            index_partition_size: None,
+            block_hash_index: false,
+            block_cache_capacity: 256 << 20,
+            block_cache: None,
+            max_open_files: None,
+            row_cache_capacity: None,
+            use_mmap: false,
+            mmap_max_file_size: 256 << 20,
+            env: Arc::new(PosixEnv),
+            prefix_extractor: None,
+            prefix_bloom_bits_per_key: 10,
+            listeners: Vec::new(),
+            rate_limiter: None,
+        }
+    }
+}
+
+impl LsmStorageOptions {
+    /// Create an SST builder writing the index, hash indexes and prefix filter of the options.
+    pub(crate) fn new_sst_builder(&self, block_size: usize) -> SsTableBuilder {
+        let mut builder = SsTableBuilder::new(block_size);
+        if let Some(partition_size) = self.index_partition_size {
+            builder = builder.with_partitioned_index(partition_size);
+        }
+        if self.block_hash_index {
+            builder = builder.with_block_hash_index();
+        }
+        if let Some(ref extractor) = self.prefix_extractor {
+            builder = builder.with_prefix_bloom(extractor.clone(), self.prefix_bloom_bits_per_key);
+        }
+        builder
+    }
+}
+
+/// Options for a single scan.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Maximum number of blocks an SST iterator reads at a time once the scan is found to be
+    /// sequential. Zero or one disables readahead.
+    pub readahead_blocks: usize,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            readahead_blocks: 16,
+        }
+    }
+}
+
+/// Options for [`LsmStorage::approximate_size`] and [`LsmStorage::approximate_count`].
+#[derive(Debug, Clone, Default)]
+pub struct ApproximateOptions {
+    /// Include the entries of the memtables, which are not on disk yet.
+    pub include_memtables: bool,
+}
+
+/// Options for [`LsmStorage::bulk_load`].
+#[derive(Debug, Clone)]
+pub struct BulkLoadOptions {
+    /// Maximum size in bytes of the keys and values buffered in memory. Once reached, the buffer
+    /// is sorted and spilled to a temporary run on disk.
+    pub memory_limit: usize,
+    /// Target size of the SSTs written.
+    pub target_sst_size: usize,
+}
+
+impl Default for BulkLoadOptions {
+    fn default() -> Self {
+        Self {
+            memory_limit: 64 << 20,
+            target_sst_size: 64 << 20,
         }
     }
 }
@@ -44,10 +189,14 @@ impl LsmStorageInner {
 /// The storage interface of the LSM tree.
 pub struct LsmStorage {
     pub(crate) inner: Arc<RwLock<Arc<LsmStorageInner>>>,
-    flush_lock: Mutex<()>,
+    pub(crate) flush_lock: Mutex<()>,
     path: PathBuf,
     pub(crate) block_cache: Arc<BlockCache>,
+    pub(crate) table_cache: TableCache,
+    pub(crate) row_cache: Option<RowCache>,
     next_sst_id: AtomicUsize,
+    pub(crate) options: LsmStorageOptions,
+    pub(crate) stats: Statistics,
 }
 
 impl LsmStorage {
@@ -56,18 +205,102 @@ impl LsmStorage {
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
     }
 
+    /// Get the id `next_sst_id` will return next, without allocating it.
+    pub(crate) fn peek_next_sst_id(&self) -> usize {
+        self.next_sst_id.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_next_sst_id(&self, id: usize) {
+        self.next_sst_id
+            .store(id, std::sync::atomic::Ordering::SeqCst)
+    }
+
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
+        Self::open_with_options(path, LsmStorageOptions::default())
+    }
+
+    /// Open the storage in a directory, recovering the SSTs and blob files listed in its manifest.
+    /// Data that had not been persisted with `sync` is lost.
+    pub fn open_with_options(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
+        options.env.create_dir_all(path.as_ref())?;
+        let storage = Self {
             inner: Arc::new(RwLock::new(Arc::new(LsmStorageInner::create()))),
             flush_lock: Mutex::new(()),
             path: path.as_ref().to_path_buf(),
-            block_cache: Arc::new(BlockCache::new(1 << 20)), // 4GB block cache,
+            block_cache: Arc::new(match options.block_cache {
+                Some(ref shared) => BlockCache::with_shared(shared.clone()),
+                None => BlockCache::new(options.block_cache_capacity),
+            }),
+            table_cache: TableCache::new(options.max_open_files),
+            row_cache: options.row_cache_capacity.map(RowCache::new),
             next_sst_id: AtomicUsize::new(1),
-        })
+            options,
+            stats: Statistics::default(),
+        };
+        storage.recover()?;
+        Ok(storage)
+    }
+
+    /// Get the counters of the block cache. If the cache is shared, the counters cover all
+    /// storage instances using it.
+    pub fn block_cache_stats(&self) -> BlockCacheStats {
+        self.block_cache.stats()
+    }
+
+    /// Get a snapshot of the statistics of the storage: counters of the operations, latency
+    /// histograms, and the files of each level.
+    pub fn stats(&self) -> StorageStats {
+        let snapshot = {
+            let guard = self.inner.read();
+            Arc::clone(&guard)
+        }; // drop global lock here
+
+        let mut stats = self.stats.snapshot();
+        stats.block_cache = self.block_cache.stats();
+        let levels = std::iter::once(&snapshot.l0_sstables).chain(snapshot.levels.iter());
+        let mut levels: Vec<_> = levels
+            .map(|tables| LevelStats {
+                num_files: tables.len(),
+                size: tables.iter().map(|table| table.file_size()).sum(),
+                bytes_read: tables.iter().map(|table| table.bytes_read()).sum(),
+                bytes_written: 0,
+            })
+            .collect();
+        levels.resize(NUM_LEVELS + 1, LevelStats::default());
+        for (level, bytes_written) in levels.iter_mut().zip(&self.stats.level_bytes_written) {
+            level.bytes_written = bytes_written.get();
+        }
+        stats.levels = levels;
+        stats
     }
 
     /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        let start = Instant::now();
+        self.stats.num_gets.inc();
+        let value = self.get_cached(key);
+        self.stats.get_latency.record_since(start);
+        value
+    }
+
+    /// Get a key through the row cache, if there is one.
+    fn get_cached(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        let Some(ref row_cache) = self.row_cache else {
+            return self.get_uncached(key);
+        };
+        if let Some(value) = row_cache.get(key) {
+            self.stats.get_hits_row_cache.inc();
+            return Ok(value);
+        }
+        // Take the version before reading, so that a concurrent write prevents caching the result.
+        let version = row_cache.version(key);
+        let value = self.get_uncached(key)?;
+        row_cache.fill(key, version, value.clone());
+        Ok(value)
+    }
+
+    /// Get a key from the memtables and SSTs, bypassing the row cache.
+    fn get_uncached(&self, key: &[u8]) -> Result<Option<Bytes>> {
         let snapshot = {
             let guard = self.inner.read();
             Arc::clone(&guard)
@@ -75,6 +308,7 @@ impl LsmStorage {
 
         // Search on the current memtable.
         if let Some(value) = snapshot.memtable.get(key) {
+            self.stats.get_hits_memtable.inc();
             if value.is_empty() {
                 // found tomestone, return key not exists
                 return Ok(None);
@@ -84,6 +318,7 @@ impl LsmStorage {
         // Search on immutable memtables.
         for memtable in snapshot.imm_memtables.iter().rev() {
             if let Some(value) = memtable.get(key) {
+                self.stats.get_hits_memtable.inc();
                 if value.is_empty() {
                     // found tomestone, return key not exists
                     return Ok(None);
@@ -91,27 +326,201 @@ impl LsmStorage {
                 return Ok(Some(value));
             }
         }
-        let mut iters = Vec::with_capacity(snapshot.l0_sstables.len());
-        for table in snapshot.l0_sstables.iter().rev() {
-            iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
-                table.clone(),
-                key,
-            )?));
+        match self.get_from_sstables(&snapshot, key)? {
+            Some(raw) => {
+                self.stats.get_hits_sst.inc();
+                resolve_value(&snapshot.blob_files, &raw)
+            }
+            None => {
+                self.stats.get_misses.inc();
+                Ok(None)
+            }
         }
-        let iter = MergeIterator::create(iters);
-        if iter.is_valid() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+    }
+
+    /// Get the newest entry of a key in the SSTs as it is stored on disk, without resolving blob
+    /// pointers. Tombstones are returned as empty values.
+    pub(crate) fn get_from_sstables(
+        &self,
+        snapshot: &LsmStorageInner,
+        key: &[u8],
+    ) -> Result<Option<Bytes>> {
+        // L0 tables may overlap, so probe all of them from the latest to the earliest.
+        let l0_candidates = snapshot
+            .l0_sstables
+            .iter()
+            .rev()
+            .filter(|table| table.may_contain_key(key));
+        // Tables in the other levels are sorted and disjoint, at most one of them may contain the
+        // key.
+        let level_candidates = snapshot.levels.iter().filter_map(|level| {
+            let idx = level.partition_point(|table| table.last_key().as_ref() < key);
+            level.get(idx).filter(|table| table.may_contain_key(key))
+        });
+        for meta in l0_candidates.chain(level_candidates) {
+            if let Some(value) = self.open_sst(meta)?.get(key)? {
+                return Ok(Some(value));
+            }
         }
         Ok(None)
     }
 
+    /// Get a batch of keys from a single snapshot of the storage, returning their values in the
+    /// order of `keys`. Cheaper than calling `get` for each key: the keys are looked up in sorted
+    /// order, so that each SST is probed once and each block is read once for all of its keys.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        let start = Instant::now();
+        self.stats.num_gets.add(keys.len() as u64);
+        let values = self.multi_get_cached(keys);
+        self.stats.multi_get_latency.record_since(start);
+        values
+    }
+
+    /// Get a batch of keys through the row cache, if there is one.
+    fn multi_get_cached(&self, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        let Some(ref row_cache) = self.row_cache else {
+            return self.multi_get_uncached(keys);
+        };
+        let mut values = vec![None; keys.len()];
+        let mut missed = Vec::new();
+        for (idx, key) in keys.iter().enumerate() {
+            match row_cache.get(key) {
+                Some(value) => values[idx] = value,
+                None => missed.push(idx),
+            }
+        }
+        self.stats
+            .get_hits_row_cache
+            .add((keys.len() - missed.len()) as u64);
+        // Take the versions before reading, so that concurrent writes prevent caching the results.
+        let versions: Vec<_> = missed
+            .iter()
+            .map(|idx| row_cache.version(keys[*idx]))
+            .collect();
+        let missed_keys: Vec<_> = missed.iter().map(|idx| keys[*idx]).collect();
+        let missed_values = self.multi_get_uncached(&missed_keys)?;
+        for ((idx, version), value) in missed.into_iter().zip(versions).zip(missed_values) {
+            row_cache.fill(keys[idx], version, value.clone());
+            values[idx] = value;
+        }
+        Ok(values)
+    }
+
+    /// Get a batch of keys from the memtables and SSTs, bypassing the row cache.
+    fn multi_get_uncached(&self, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        let snapshot = {
+            let guard = self.inner.read();
+            Arc::clone(&guard)
+        }; // drop global lock here
+
+        let mut order: Vec<_> = (0..keys.len()).collect();
+        order.sort_by_key(|idx| keys[*idx]);
+        let mut values = vec![None; keys.len()];
+        // Indices of the keys not found in the memtables, sorted by key.
+        let mut pending = Vec::new();
+        'keys: for idx in order {
+            let memtables =
+                std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter().rev());
+            for memtable in memtables {
+                if let Some(value) = memtable.get(keys[idx]) {
+                    self.stats.get_hits_memtable.inc();
+                    // An empty value is a tombstone, the key does not exist.
+                    values[idx] = Some(value).filter(|value| !value.is_empty());
+                    continue 'keys;
+                }
+            }
+            pending.push(idx);
+        }
+        let num_pending = pending.len();
+        let found = self.multi_get_from_sstables(&snapshot, keys, pending)?;
+        self.stats.get_hits_sst.add(found.len() as u64);
+        self.stats
+            .get_misses
+            .add((num_pending - found.len()) as u64);
+        for (idx, raw) in found {
+            values[idx] = resolve_value(&snapshot.blob_files, &raw)?;
+        }
+        Ok(values)
+    }
+
+    /// Batched `get_from_sstables`: look up the keys at the indices in `pending`, which are sorted
+    /// by key, and return the entries found as pairs of key index and stored value.
+    fn multi_get_from_sstables(
+        &self,
+        snapshot: &LsmStorageInner,
+        keys: &[&[u8]],
+        mut pending: Vec<usize>,
+    ) -> Result<Vec<(usize, Bytes)>> {
+        let mut found = Vec::new();
+        // L0 tables may overlap, so probe all of them from the latest to the earliest.
+        for meta in snapshot.l0_sstables.iter().rev() {
+            pending = self.probe_sst(meta, keys, pending, &mut found)?;
+        }
+        // Tables in the other levels are sorted and disjoint. As the keys are sorted too, the keys
+        // falling into the same table are adjacent.
+        for level in &snapshot.levels {
+            let mut remaining = Vec::new();
+            let mut start = 0;
+            while start < pending.len() {
+                let key = keys[pending[start]];
+                let table_idx = level.partition_point(|table| table.last_key().as_ref() < key);
+                let Some(meta) = level.get(table_idx) else {
+                    remaining.extend_from_slice(&pending[start..]);
+                    break;
+                };
+                let end = start
+                    + pending[start..]
+                        .partition_point(|idx| keys[*idx] <= meta.last_key().as_ref());
+                let batch = pending[start..end].to_vec();
+                remaining.extend(self.probe_sst(meta, keys, batch, &mut found)?);
+                start = end;
+            }
+            pending = remaining;
+        }
+        Ok(found)
+    }
+
+    /// Look up the keys at the indices in `pending` in one SST. Moves the entries found to `found`
+    /// and returns the indices of the other keys, still sorted.
+    fn probe_sst(
+        &self,
+        meta: &SsTableMeta,
+        keys: &[&[u8]],
+        pending: Vec<usize>,
+        found: &mut Vec<(usize, Bytes)>,
+    ) -> Result<Vec<usize>> {
+        // Avoid opening the table if none of the keys falls into its range.
+        if !pending.iter().any(|idx| meta.may_contain_key(keys[*idx])) {
+            return Ok(pending);
+        }
+        let batch: Vec<_> = pending.iter().map(|idx| keys[*idx]).collect();
+        let values = self.open_sst(meta)?.multi_get(&batch)?;
+        let mut remaining = Vec::new();
+        for (idx, value) in pending.into_iter().zip(values) {
+            match value {
+                Some(value) => found.push((idx, value)),
+                None => remaining.push(idx),
+            }
+        }
+        Ok(remaining)
+    }
+
     /// Put a key-value pair into the storage by writing into the current memtable.
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
         assert!(!value.is_empty(), "value cannot be empty");
         assert!(!key.is_empty(), "key cannot be empty");
 
+        let start = Instant::now();
         let guard = self.inner.read();
         guard.memtable.put(key, value);
+        if let Some(ref row_cache) = self.row_cache {
+            row_cache.invalidate(key);
+        }
+        self.stats.num_puts.inc();
+        self.stats
+            .user_bytes_written
+            .add((key.len() + value.len()) as u64);
+        self.stats.write_latency.record_since(start);
 
         Ok(())
     }
@@ -120,25 +529,173 @@ impl LsmStorage {
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         assert!(!key.is_empty(), "key cannot be empty");
 
+        let start = Instant::now();
         let guard = self.inner.read();
         guard.memtable.put(key, b"");
+        if let Some(ref row_cache) = self.row_cache {
+            row_cache.invalidate(key);
+        }
+        self.stats.num_deletes.inc();
+        self.stats.user_bytes_written.add(key.len() as u64);
+        self.stats.write_latency.record_since(start);
 
         Ok(())
     }
 
+    /// Get the file system the storage runs on.
+    pub fn env(&self) -> &dyn Env {
+        self.options.env.as_ref()
+    }
+
+    /// Get the directory of the storage.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub(crate) fn path_of_sst(&self, id: usize) -> PathBuf {
         self.path.join(format!("{:05}.sst", id))
     }
 
+    /// Open the file of an SST, following the read mode of the options.
+    pub(crate) fn open_sst_file(&self, id: usize) -> Result<FileObject> {
+        let path = self.path_of_sst(id);
+        if self.options.use_mmap {
+            FileObject::open_mmap(self.env(), &path, self.options.mmap_max_file_size)
+        } else {
+            FileObject::open(self.env(), &path)
+        }
+    }
+
+    /// Get an SST from the table cache, opening its file if the table is not cached.
+    pub(crate) fn open_sst(&self, meta: &SsTableMeta) -> Result<Arc<SsTable>> {
+        self.table_cache.try_get_with(meta.sst_id(), || {
+            Ok(Arc::new(SsTable::open(
+                meta.sst_id(),
+                Some(self.block_cache.clone()),
+                self.open_sst_file(meta.sst_id())?
+                    .with_read_counter(meta.read_counter()),
+            )?))
+        })
+    }
+
+    /// Add a newly built SST to the table cache, and return the metadata to keep in the LSM tree.
+    pub(crate) fn add_new_sst(&self, table: SsTable) -> Arc<SsTableMeta> {
+        let meta = Arc::new(SsTableMeta::new(&table));
+        // A built table reads with `pread`. With mmap enabled, leave it to `open_sst` to map it on
+        // the first read.
+        if !self.options.use_mmap {
+            self.table_cache.insert(Arc::new(table));
+        }
+        meta
+    }
+
+    /// Call `f` on each registered event listener.
+    pub(crate) fn notify(&self, f: impl Fn(&dyn EventListener)) {
+        for listener in &self.options.listeners {
+            f(listener.as_ref());
+        }
+    }
+
+    pub(crate) fn notify_table_file_created(
+        &self,
+        table: &SsTableMeta,
+        level: usize,
+        reason: TableFileCreationReason,
+    ) {
+        let info = TableFileCreationInfo {
+            sst_id: table.sst_id(),
+            path: self.path_of_sst(table.sst_id()),
+            level,
+            file_size: table.file_size(),
+            num_entries: table.properties().num_entries,
+            reason,
+        };
+        self.notify(|listener| listener.on_table_file_created(&info));
+    }
+
+    /// Create an SST builder following the options of the storage.
+    pub(crate) fn new_sst_builder(&self, block_size: usize) -> SsTableBuilder {
+        self.options.new_sst_builder(block_size)
+    }
+
+    /// Create an SST builder for a flush or a compaction, which writes the file through the rate
+    /// limiter in the lane of `priority`.
+    pub(crate) fn new_background_sst_builder(
+        &self,
+        block_size: usize,
+        priority: IoPriority,
+    ) -> SsTableBuilder {
+        let builder = self.new_sst_builder(block_size);
+        match self.options.rate_limiter {
+            Some(ref rate_limiter) => builder.with_rate_limiter(rate_limiter.clone(), priority),
+            None => builder,
+        }
+    }
+
+    pub(crate) fn path_of_blob(&self, id: usize) -> PathBuf {
+        self.path.join(format!("{:05}.blob", id))
+    }
+
+    /// Write the content of a memtable into an SST builder. Values larger than the blob threshold
+    /// are written to a new blob file, through the rate limiter in the flush lane, which is
+    /// returned if any value has been separated.
+    fn flush_memtable(
+        &self,
+        memtable: &MemTable,
+        builder: &mut SsTableBuilder,
+    ) -> Result<Option<BlobFile>> {
+        let mut blob_builder: Option<BlobFileBuilder> = None;
+        let mut iter = memtable.scan(Bound::Unbounded, Bound::Unbounded);
+        let mut buf = Vec::new();
+        while iter.is_valid() {
+            let (key, value) = (iter.key(), iter.value());
+            buf.clear();
+            if value.is_empty() {
+                // tombstones are stored as is
+            } else if self
+                .options
+                .blob_threshold
+                .is_some_and(|threshold| value.len() >= threshold)
+            {
+                let blob_builder = blob_builder.get_or_insert_with(|| {
+                    let blob_builder = BlobFileBuilder::new(self.next_sst_id());
+                    match self.options.rate_limiter {
+                        Some(ref rate_limiter) => {
+                            blob_builder.with_rate_limiter(rate_limiter.clone(), IoPriority::Flush)
+                        }
+                        None => blob_builder,
+                    }
+                });
+                StoredValue::Blob(blob_builder.add(key, value)).encode(&mut buf);
+            } else {
+                StoredValue::Inline(value).encode(&mut buf);
+            }
+            builder.add_with_value_size(key, &buf, value.len());
+            iter.next()?;
+        }
+        blob_builder
+            .map(|blob_builder| {
+                let path = self.path_of_blob(blob_builder.id());
+                blob_builder.build(self.env(), path)
+            })
+            .transpose()
+    }
+
     /// Persist data to disk.
     ///
     /// In day 3: flush the current memtable to disk as L0 SST.
     /// In day 6: call `fsync` on WAL.
     pub fn sync(&self) -> Result<()> {
-        let _flush_lock = self.flush_lock.lock();
+        let result = self.flush();
+        if let Err(ref e) = result {
+            self.notify(|listener| listener.on_background_error(BackgroundErrorReason::Flush, e));
+        }
+        result
+    }
 
-        let flush_memtable;
-        let sst_id: usize;
+    /// Flush the memtable to a new L0 table.
+    fn flush(&self) -> Result<()> {
+        let _flush_lock = self.flush_lock.lock();
 
         // Move mutable memtable to immutable memtables.
         {
@@ -146,8 +703,6 @@ impl LsmStorage {
             // Swap the current memtable with a new one.
             let mut snapshot = guard.as_ref().clone();
             let memtable = std::mem::replace(&mut snapshot.memtable, Arc::new(MemTable::create()));
-            flush_memtable = memtable.clone();
-            sst_id = self.next_sst_id();
             // Add the memtable to the immutable memtables.
             snapshot.imm_memtables.push(memtable);
             // Update the snapshot.
@@ -156,28 +711,74 @@ impl LsmStorage {
 
         // At this point, the old memtable should be disabled for write, and all write threads
         // should be operating on the new memtable. We can safely flush the old memtable to
-        // disk.
+        // disk. A memtable whose flush failed is still in the immutable memtables, so flush them
+        // all from the earliest, which keeps the L0 tables in order.
+        let imm_memtables = self.inner.read().imm_memtables.clone();
+        for memtable in imm_memtables {
+            self.flush_imm_memtable(&memtable)?;
+        }
+        Ok(())
+    }
 
-        let mut builder = SsTableBuilder::new(4096);
-        flush_memtable.flush(&mut builder)?;
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+    /// Flush an immutable memtable to a new L0 table, and remove it from the immutable memtables
+    /// once the table is in the manifest. On error, the memtable is left in place.
+    fn flush_imm_memtable(&self, memtable: &Arc<MemTable>) -> Result<()> {
+        let start = Instant::now();
+        // An empty memtable produces no SST.
+        let flushed = if memtable.is_empty() {
+            None
+        } else {
+            let sst_id = self.next_sst_id();
+            let mut builder =
+                self.new_background_sst_builder(self.options.block_size, IoPriority::Flush);
+            // The blob file is written before the SST so that pointers never dangle.
+            let blob_file = self.flush_memtable(memtable, &mut builder)?;
+            let sst = self.add_new_sst(builder.build_with_env(
+                sst_id,
+                Some(self.block_cache.clone()),
+                self.env(),
+                self.path_of_sst(sst_id),
+            )?);
+            Some((sst, blob_file))
+        };
 
-        // Add the flushed L0 table to the list.
-        {
-            let mut guard = self.inner.write();
-            let mut snapshot = guard.as_ref().clone();
-            // Remove the memtable from the immutable memtables.
-            snapshot.imm_memtables.pop();
-            // Add L0 table
-            snapshot.l0_sstables.push(sst);
-            // Update the snapshot.
-            *guard = Arc::new(snapshot);
+        // Add the flushed L0 table to the list. Other changes to the list of tables hold the flush
+        // lock, so the snapshot cannot change until it is updated.
+        let mut snapshot = self.inner.read().as_ref().clone();
+        // Remove the memtable from the immutable memtables.
+        snapshot
+            .imm_memtables
+            .retain(|imm_memtable| !Arc::ptr_eq(imm_memtable, memtable));
+        let Some((sst, blob_file)) = flushed else {
+            *self.inner.write() = Arc::new(snapshot);
+            return Ok(());
+        };
+        let bytes_flushed = sst.file_size() + blob_file.as_ref().map_or(0, BlobFile::size);
+        let info = FlushJobInfo {
+            sst_id: sst.sst_id(),
+            blob_file_id: blob_file.as_ref().map(BlobFile::id),
+            num_entries: sst.properties().num_entries,
+            bytes_written: bytes_flushed,
+            duration: start.elapsed(),
+        };
+        // Add L0 table
+        snapshot.l0_sstables.push(sst.clone());
+        if let Some(blob_file) = blob_file {
+            let mut blob_files = snapshot.blob_files.as_ref().clone();
+            blob_files.insert(blob_file.id(), Arc::new(blob_file));
+            snapshot.blob_files = Arc::new(blob_files);
         }
+        // The flush is durable once the new table is in the manifest.
+        self.write_manifest(&snapshot)?;
+        // Update the snapshot.
+        *self.inner.write() = Arc::new(snapshot);
 
+        self.stats.num_flushes.inc();
+        self.stats.bytes_flushed.add(bytes_flushed);
+        self.stats.level_bytes_written[0].add(bytes_flushed);
+        self.stats.flush_latency.record_since(start);
+        self.notify_table_file_created(&sst, 0, TableFileCreationReason::Flush);
+        self.notify(|listener| listener.on_flush_completed(&info));
         Ok(())
     }
 
@@ -187,6 +788,44 @@ impl LsmStorage {
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
     ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_with_options(lower, upper, &ScanOptions::default())
+    }
+
+    /// Create an iterator over a range of keys, with options for this scan.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ScanOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_filtered(lower, upper, options, |_| true)
+    }
+
+    /// Create an iterator over the keys starting with `prefix`. With a prefix extractor, the SSTs
+    /// whose prefix bloom filter does not hold the prefix are skipped without being opened.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<FusedIterator<LsmIterator>> {
+        let upper = prefix_upper_bound(prefix);
+        let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let extractor = self.options.prefix_extractor.as_deref();
+        self.scan_filtered(
+            Bound::Included(prefix),
+            upper,
+            &ScanOptions::default(),
+            |table| table.may_contain_prefix(extractor, prefix),
+        )
+    }
+
+    /// Create an iterator over a range of keys, skipping the SSTs rejected by `table_filter` on top
+    /// of those that do not overlap with the range.
+    fn scan_filtered(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ScanOptions,
+        table_filter: impl Fn(&SsTableMeta) -> bool,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let start = Instant::now();
+        self.stats.num_scans.inc();
         let snapshot = {
             let guard = self.inner.read();
             Arc::clone(&guard)
@@ -199,31 +838,143 @@ impl LsmStorage {
         }
         let memtable_iter = MergeIterator::create(memtable_iters);
 
+        // Skip the tables that do not overlap with the range. For the sorted levels, binary search
+        // for the first table that may contain keys in the range.
+        let l0_tables = snapshot.l0_sstables.iter().rev();
+        let level_tables = snapshot.levels.iter().flat_map(|level| {
+            let start = level.partition_point(|table| match lower {
+                Bound::Included(key) => table.last_key().as_ref() < key,
+                Bound::Excluded(key) => table.last_key().as_ref() <= key,
+                Bound::Unbounded => false,
+            });
+            level[start..]
+                .iter()
+                .take_while(move |table| table.range_overlap(lower, upper))
+        });
         let mut table_iters = Vec::with_capacity(snapshot.l0_sstables.len());
-        for table in snapshot.l0_sstables.iter().rev() {
-            let iter = match lower {
-                Bound::Included(key) => {
-                    SsTableIterator::create_and_seek_to_key(table.clone(), key)?
-                }
+        for meta in l0_tables
+            .chain(level_tables)
+            .filter(|table| table.range_overlap(lower, upper) && table_filter(table))
+        {
+            let table = self.open_sst(meta)?;
+            let mut iter = match lower {
+                Bound::Included(key) => SsTableIterator::create_and_seek_to_key(table, key)?,
                 Bound::Excluded(key) => {
-                    let mut iter = SsTableIterator::create_and_seek_to_key(table.clone(), key)?;
+                    let mut iter = SsTableIterator::create_and_seek_to_key(table, key)?;
                     if iter.is_valid() && iter.key() == key {
                         iter.next()?;
                     }
                     iter
                 }
-                Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table.clone())?,
+                Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table)?,
             };
+            iter.set_readahead(options.readahead_blocks);
 
             table_iters.push(Box::new(iter));
         }
-        let table_iter = MergeIterator::create(table_iters);
+        let table_iter = BlobResolveIterator::create(
+            MergeIterator::create(table_iters),
+            snapshot.blob_files.clone(),
+            map_bound(upper),
+        )?;
 
         let iter = TwoMergeIterator::create(memtable_iter, table_iter)?;
+        let iter = FusedIterator::new(LsmIterator::new(iter, map_bound(upper))?);
+        self.stats.scan_latency.record_since(start);
+        Ok(iter)
+    }
 
-        Ok(FusedIterator::new(LsmIterator::new(
-            iter,
-            map_bound(upper),
-        )?))
+    /// Get the approximate size in bytes of the SSTs holding a range of keys, without scanning the
+    /// range. SSTs partially overlapping the range count in proportion to the part of their data
+    /// blocks in the range, so the result is only accurate up to a block per SST. Values
+    /// separated into blob files are not counted.
+    pub fn approximate_size(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ApproximateOptions,
+    ) -> Result<u64> {
+        Ok(self.approximate_range_stats(lower, upper, options)?.0)
+    }
+
+    /// Get the approximate number of entries in a range of keys, estimated like
+    /// [`LsmStorage::approximate_size`]. Overwritten and deleted entries that have not been
+    /// compacted away count as well.
+    pub fn approximate_count(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ApproximateOptions,
+    ) -> Result<u64> {
+        Ok(self.approximate_range_stats(lower, upper, options)?.1)
+    }
+
+    /// Estimate the size and the number of entries of a range.
+    fn approximate_range_stats(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ApproximateOptions,
+    ) -> Result<(u64, u64)> {
+        let snapshot = {
+            let guard = self.inner.read();
+            Arc::clone(&guard)
+        }; // drop global lock here
+
+        let (mut size, mut count) = (0, 0);
+        if options.include_memtables {
+            let memtables = std::iter::once(&snapshot.memtable).chain(&snapshot.imm_memtables);
+            for memtable in memtables {
+                let (memtable_size, memtable_count) = memtable.range_stats(lower, upper);
+                size += memtable_size;
+                count += memtable_count;
+            }
+        }
+        let tables = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flatten());
+        for meta in tables.filter(|table| table.range_overlap(lower, upper)) {
+            let fraction = self.fraction_in_range(meta, lower, upper)?;
+            size += (meta.file_size() as f64 * fraction) as u64;
+            count += (meta.properties().num_entries as f64 * fraction) as u64;
+        }
+        Ok((size, count))
+    }
+
+    /// Estimate the fraction of the data blocks of an SST that hold keys in a range. Tables fully
+    /// in the range are not opened.
+    fn fraction_in_range(
+        &self,
+        meta: &SsTableMeta,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<f64> {
+        let lower_key = match lower {
+            Bound::Included(key) | Bound::Excluded(key) if key > meta.first_key().as_ref() => {
+                Some(key)
+            }
+            _ => None,
+        };
+        let upper_key = match upper {
+            Bound::Included(key) | Bound::Excluded(key) if key < meta.last_key().as_ref() => {
+                Some(key)
+            }
+            _ => None,
+        };
+        if lower_key.is_none() && upper_key.is_none() {
+            return Ok(1.0);
+        }
+        let table = self.open_sst(meta)?;
+        let data_size = table.data_size()?;
+        let start = match lower_key {
+            Some(key) => table.approximate_offset_of(key)?,
+            None => 0,
+        };
+        let end = match upper_key {
+            Some(key) => table.approximate_offset_of(key)?,
+            None => data_size,
+        };
+        Ok(end.saturating_sub(start) as f64 / data_size as f64)
     }
 }
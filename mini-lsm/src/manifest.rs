@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use bytes::{Buf, BufMut};
+
+use crate::blob::BlobFile;
+use crate::env::{write_file, Env};
+use crate::event_listener::{BlobFileDeletionInfo, TableFileDeletionInfo};
+use crate::lsm_storage::{LsmStorage, LsmStorageInner};
+use crate::table::{SsTable, SsTableMeta};
+
+pub const MANIFEST_NAME: &str = "MANIFEST";
+const MANIFEST_TMP_NAME: &str = "MANIFEST.tmp";
+
+/// The durable state of the LSM tree: the SSTs and blob files that are part of it. The manifest is
+/// rewritten as a whole on every change. It is written to a temporary file, synced, and renamed
+/// over the previous version, so that a crash leaves either the old or the new manifest.
+///
+/// ```text
+/// | next_sst_id (u64) | l0 ids | num_levels (u32) | level ids ... | blob file ids | checksum (u32) |
+/// ```
+///
+/// Each list of ids is encoded as its length (u32) followed by the ids (u32). The checksum is the
+/// CRC32 of everything before it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The next id to allocate for an SST or a blob file.
+    pub next_sst_id: usize,
+    /// L0 SSTs, from earliest to latest.
+    pub l0_sstables: Vec<usize>,
+    /// SSTs of L1 - L6, sorted by key range.
+    pub levels: Vec<Vec<usize>>,
+    /// Blob files.
+    pub blob_files: Vec<usize>,
+}
+
+fn encode_ids(ids: &[usize], buf: &mut Vec<u8>) {
+    buf.put_u32(ids.len() as u32);
+    for id in ids {
+        buf.put_u32(*id as u32);
+    }
+}
+
+fn decode_ids(buf: &mut &[u8]) -> Result<Vec<usize>> {
+    if buf.remaining() < 4 {
+        bail!("manifest truncated");
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len * 4 {
+        bail!("manifest truncated");
+    }
+    Ok((0..len).map(|_| buf.get_u32() as usize).collect())
+}
+
+impl Manifest {
+    /// Collect the files of a snapshot of the storage.
+    pub fn from_snapshot(snapshot: &LsmStorageInner, next_sst_id: usize) -> Self {
+        let ids = |tables: &[Arc<SsTableMeta>]| tables.iter().map(|table| table.sst_id()).collect();
+        let mut blob_files: Vec<_> = snapshot.blob_files.keys().copied().collect();
+        blob_files.sort_unstable();
+        Self {
+            next_sst_id,
+            l0_sstables: ids(&snapshot.l0_sstables),
+            levels: snapshot.levels.iter().map(|level| ids(level)).collect(),
+            blob_files,
+        }
+    }
+
+    /// Get the ids of all SSTs, from L0 to the last level.
+    pub fn sst_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.l0_sstables
+            .iter()
+            .chain(self.levels.iter().flatten())
+            .copied()
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.put_u64(self.next_sst_id as u64);
+        encode_ids(&self.l0_sstables, buf);
+        buf.put_u32(self.levels.len() as u32);
+        for level in &self.levels {
+            encode_ids(level, buf);
+        }
+        encode_ids(&self.blob_files, buf);
+        let checksum = crc32fast::hash(&buf[start..]);
+        buf.put_u32(checksum);
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 12 {
+            bail!("manifest truncated");
+        }
+        let (mut buf, mut checksum) = data.split_at(data.len() - 4);
+        if crc32fast::hash(buf) != checksum.get_u32() {
+            bail!("manifest checksum mismatch");
+        }
+        let next_sst_id = buf.get_u64() as usize;
+        let l0_sstables = decode_ids(&mut buf)?;
+        if buf.remaining() < 4 {
+            bail!("manifest truncated");
+        }
+        let num_levels = buf.get_u32() as usize;
+        let levels = (0..num_levels)
+            .map(|_| decode_ids(&mut buf))
+            .collect::<Result<_>>()?;
+        let blob_files = decode_ids(&mut buf)?;
+        Ok(Self {
+            next_sst_id,
+            l0_sstables,
+            levels,
+            blob_files,
+        })
+    }
+
+    /// Atomically replace the manifest in `dir`.
+    pub fn write(&self, env: &dyn Env, dir: &Path) -> Result<()> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        let tmp_path = dir.join(MANIFEST_TMP_NAME);
+        write_file(env, &tmp_path, &buf)?;
+        env.rename(&tmp_path, &dir.join(MANIFEST_NAME))?;
+        // Persist the rename, together with the files created since the last manifest.
+        env.sync_dir(dir)
+    }
+
+    /// Read the manifest in `dir`, or `None` if the directory does not hold a database.
+    pub fn read(env: &dyn Env, dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(MANIFEST_NAME);
+        if !env.list(dir)?.contains(&path) {
+            return Ok(None);
+        }
+        let file = env.open(&path)?;
+        let data = file.read_at(0, file.size())?;
+        Self::decode(&data).map(Some)
+    }
+}
+
+/// Get the extension and the id of an SST or a blob file, `None` for other files.
+fn table_file_id(path: &Path) -> Option<(&str, usize)> {
+    let ext = path.extension().and_then(|ext| ext.to_str())?;
+    let id = path.file_stem()?.to_str()?.parse::<usize>().ok()?;
+    matches!(ext, "sst" | "blob").then_some((ext, id))
+}
+
+impl LsmStorage {
+    /// Write the manifest for a new snapshot of the storage. Must be called with the flush lock
+    /// held, before the snapshot is installed.
+    pub(crate) fn write_manifest(&self, snapshot: &LsmStorageInner) -> Result<()> {
+        Manifest::from_snapshot(snapshot, self.peek_next_sst_id()).write(self.env(), self.path())
+    }
+
+    /// Load the files listed in the manifest, and remove the files left behind by a crash before
+    /// they were added to the manifest. A directory without a manifest must not hold any table, it
+    /// is initialized with an empty manifest.
+    pub(crate) fn recover(&self) -> Result<()> {
+        let Some(manifest) = Manifest::read(self.env(), self.path())? else {
+            // The manifest is lost, removing the tables would destroy the data.
+            if let Some(path) = self
+                .env()
+                .list(self.path())?
+                .into_iter()
+                .find(|path| table_file_id(path).is_some())
+            {
+                bail!("{} exists but the manifest is missing", path.display());
+            }
+            // Tables written from now on are orphans until a manifest lists them.
+            return self.write_manifest(&self.inner.read());
+        };
+        let open_sst = |id: usize| -> Result<Arc<SsTableMeta>> {
+            let table = SsTable::open(id, Some(self.block_cache.clone()), self.open_sst_file(id)?)
+                .with_context(|| format!("failed to open SST {}", id))?;
+            Ok(self.add_new_sst(table))
+        };
+        let l0_sstables = manifest
+            .l0_sstables
+            .iter()
+            .map(|id| open_sst(*id))
+            .collect::<Result<Vec<_>>>()?;
+        let levels = manifest
+            .levels
+            .iter()
+            .map(|level| level.iter().map(|id| open_sst(*id)).collect())
+            .collect::<Result<Vec<_>>>()?;
+        let mut blob_files = HashMap::new();
+        for id in &manifest.blob_files {
+            let blob_file = BlobFile::open(self.env(), *id, &self.path_of_blob(*id))
+                .with_context(|| format!("failed to open blob file {}", id))?;
+            blob_files.insert(*id, Arc::new(blob_file));
+        }
+
+        let live: HashSet<_> = manifest
+            .sst_ids()
+            .chain(manifest.blob_files.iter().copied())
+            .collect();
+        self.remove_orphan_files(&live, manifest.next_sst_id)?;
+        self.set_next_sst_id(manifest.next_sst_id);
+        let mut guard = self.inner.write();
+        let mut snapshot = guard.as_ref().clone();
+        snapshot.l0_sstables = l0_sstables;
+        snapshot.levels = levels;
+        snapshot.blob_files = Arc::new(blob_files);
+        *guard = Arc::new(snapshot);
+        Ok(())
+    }
+
+    /// Remove the SSTs and blob files allocated after the manifest was written, i.e. with an id
+    /// of at least `next_sst_id`, that are not in `live`, and any unfinished manifest.
+    fn remove_orphan_files(&self, live: &HashSet<usize>, next_sst_id: usize) -> Result<()> {
+        for path in self.env().list(self.path())? {
+            let table_file = table_file_id(&path);
+            let is_orphan = match table_file {
+                Some((_, id)) => id >= next_sst_id && !live.contains(&id),
+                None => path.file_name() == Some(MANIFEST_TMP_NAME.as_ref()),
+            };
+            if is_orphan {
+                self.env().delete(&path)?;
+                match table_file {
+                    Some(("sst", sst_id)) => {
+                        let info = TableFileDeletionInfo { sst_id, path };
+                        self.notify(|listener| listener.on_table_file_deleted(&info));
+                    }
+                    Some((_, blob_file_id)) => {
+                        let info = BlobFileDeletionInfo { blob_file_id, path };
+                        self.notify(|listener| listener.on_blob_file_deleted(&info));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
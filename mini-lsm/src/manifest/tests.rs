@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use super::*;
+use crate::env::MemEnv;
+
+fn manifest() -> Manifest {
+    Manifest {
+        next_sst_id: 233,
+        l0_sstables: vec![5, 7, 9],
+        levels: vec![vec![1, 2], vec![], vec![3]],
+        blob_files: vec![4, 8],
+    }
+}
+
+#[test]
+fn test_manifest_encode_decode() {
+    let mut buf = Vec::new();
+    manifest().encode(&mut buf);
+    assert_eq!(Manifest::decode(&buf).unwrap(), manifest());
+    assert_eq!(
+        manifest().sst_ids().collect::<Vec<_>>(),
+        vec![5, 7, 9, 1, 2, 3]
+    );
+}
+
+#[test]
+fn test_manifest_corruption() {
+    let mut buf = Vec::new();
+    manifest().encode(&mut buf);
+    for len in 0..buf.len() {
+        assert!(Manifest::decode(&buf[..len]).is_err());
+    }
+    for idx in 0..buf.len() {
+        let mut corrupted = buf.clone();
+        corrupted[idx] ^= 0x10;
+        assert!(Manifest::decode(&corrupted).is_err());
+    }
+}
+
+#[test]
+fn test_manifest_write_read() {
+    let env = MemEnv::new();
+    let dir = Path::new("/db");
+    assert_eq!(Manifest::read(&env, dir).unwrap(), None);
+    manifest().write(&env, dir).unwrap();
+    assert_eq!(Manifest::read(&env, dir).unwrap(), Some(manifest()));
+    let updated = Manifest {
+        next_sst_id: 234,
+        ..manifest()
+    };
+    updated.write(&env, dir).unwrap();
+    assert_eq!(Manifest::read(&env, dir).unwrap(), Some(updated));
+    assert_eq!(env.list(dir).unwrap(), vec![dir.join(MANIFEST_NAME)]);
+}
@@ -42,6 +42,11 @@ impl MemTable {
             .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
     }
 
+    /// Check if the mem-table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
     /// Get an iterator over a range of keys.
     pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> MemTableIterator {
         let (lower, upper) = (map_bound(lower), map_bound(upper));
@@ -56,6 +61,16 @@ impl MemTable {
         iter
     }
 
+    /// Get the total size of the keys and values in a range, and the number of entries, by walking
+    /// the range.
+    pub fn range_stats(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> (u64, u64) {
+        let range = (map_bound(lower), map_bound(upper));
+        self.map.range(range).fold((0, 0), |(size, count), entry| {
+            let entry_size = entry.key().len() + entry.value().len();
+            (size + entry_size as u64, count + 1)
+        })
+    }
+
     /// Flush the mem-table to SSTable.
     pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
         for entry in self.map.iter() {
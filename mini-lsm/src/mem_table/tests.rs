@@ -93,3 +93,24 @@ fn test_memtable_iter() {
         assert!(!iter.is_valid());
     }
 }
+
+#[test]
+fn test_memtable_range_stats() {
+    use std::ops::Bound;
+    let memtable = MemTable::create();
+    memtable.put(b"key1", b"value1");
+    memtable.put(b"key2", b"value2");
+    memtable.put(b"key3", b"");
+    assert_eq!(
+        memtable.range_stats(Bound::Unbounded, Bound::Unbounded),
+        (24, 3)
+    );
+    assert_eq!(
+        memtable.range_stats(Bound::Excluded(b"key1"), Bound::Included(b"key3")),
+        (14, 2)
+    );
+    assert_eq!(
+        memtable.range_stats(Bound::Included(b"key4"), Bound::Unbounded),
+        (0, 0)
+    );
+}
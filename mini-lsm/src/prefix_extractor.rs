@@ -0,0 +1,61 @@
+use std::fmt;
+
+/// Extracts the prefix of a key, for the prefix bloom filters of the SSTs.
+///
+/// The prefix of a key must be a prefix of the key, and every key that starts with the prefix of
+/// some key must be in the domain and have the same prefix. Then a scan for a prefix that is in
+/// the domain only visits keys with the same prefix, and may skip the SSTs whose filter does not
+/// hold it.
+pub trait PrefixExtractor: Send + Sync + fmt::Debug {
+    /// Name of the extractor, stored in the SSTs. Filters built by an extractor of another name
+    /// are ignored, so the name must change whenever the prefixes do.
+    fn name(&self) -> &str;
+
+    /// Check if a key has a prefix. Keys out of the domain are not added to the filters.
+    fn in_domain(&self, key: &[u8]) -> bool;
+
+    /// Get the prefix of a key in the domain.
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8];
+}
+
+/// Takes the first `len` bytes of the keys as their prefix. Shorter keys have no prefix.
+#[derive(Debug)]
+pub struct FixedPrefixExtractor {
+    len: usize,
+    name: String,
+}
+
+impl FixedPrefixExtractor {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            name: format!("fixed:{}", len),
+        }
+    }
+}
+
+impl PrefixExtractor for FixedPrefixExtractor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_domain(&self, key: &[u8]) -> bool {
+        key.len() >= self.len
+    }
+
+    fn transform<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..self.len]
+    }
+}
+
+/// Get the smallest key that is greater than all keys starting with `prefix`, or `None` if there
+/// is no such key, i.e. the prefix is empty or only made of `0xff` bytes.
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut upper = prefix[..=end].to_vec();
+    upper[end] += 1;
+    Some(upper)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,18 @@
+use super::*;
+
+#[test]
+fn test_fixed_prefix_extractor() {
+    let extractor = FixedPrefixExtractor::new(4);
+    assert_eq!(extractor.name(), "fixed:4");
+    assert!(!extractor.in_domain(b"abc"));
+    assert!(extractor.in_domain(b"abcd"));
+    assert_eq!(extractor.transform(b"abcdef"), b"abcd");
+}
+
+#[test]
+fn test_prefix_upper_bound() {
+    assert_eq!(prefix_upper_bound(b"abc"), Some(b"abd".to_vec()));
+    assert_eq!(prefix_upper_bound(b"ab\xff\xff"), Some(b"ac".to_vec()));
+    assert_eq!(prefix_upper_bound(b"\xff\xff"), None);
+    assert_eq!(prefix_upper_bound(b""), None);
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
+
+/// The bucket holds the tokens of this fraction of a second, which bounds the size of a burst.
+const REFILLS_PER_SECOND: u64 = 10;
+
+/// Minimum size of a burst, so that files are not written in tiny chunks at low rates.
+const MIN_BURST_BYTES: u64 = 4096;
+
+/// The lane of an I/O request. Flushes free memory for writes, so waiting flush requests are
+/// served before compaction requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    Flush,
+    Compaction,
+}
+
+impl IoPriority {
+    fn idx(self) -> usize {
+        match self {
+            IoPriority::Flush => 0,
+            IoPriority::Compaction => 1,
+        }
+    }
+}
+
+struct State {
+    bytes_per_second: u64,
+    /// Tokens in the bucket, in bytes.
+    available: u64,
+    last_refill: Instant,
+    /// Number of requests waiting in each lane.
+    waiting: [usize; 2],
+    /// Number of bytes granted in each lane.
+    total_bytes: [u64; 2],
+}
+
+impl State {
+    fn capacity(&self) -> u64 {
+        (self.bytes_per_second / REFILLS_PER_SECOND).max(MIN_BURST_BYTES)
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        let tokens = (elapsed.as_nanos() * self.bytes_per_second as u128 / 1_000_000_000) as u64;
+        if tokens > 0 {
+            self.available = (self.available + tokens).min(self.capacity());
+            self.last_refill = now;
+        }
+    }
+}
+
+/// A token bucket limiting the rate of background I/O, so that flushes and compactions do not
+/// saturate the disk. It can be shared by several storage instances, and its rate changed while
+/// it is in use.
+pub struct RateLimiter {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter granting `bytes_per_second` bytes per second. Zero disables the
+    /// limit.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let mut state = State {
+            bytes_per_second,
+            available: 0,
+            last_refill: Instant::now(),
+            waiting: [0; 2],
+            total_bytes: [0; 2],
+        };
+        state.available = state.capacity();
+        Self {
+            state: Mutex::new(state),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Change the rate. Requests already waiting continue at the new rate.
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        let mut state = self.state.lock();
+        state.refill();
+        state.bytes_per_second = bytes_per_second;
+        state.available = state.available.min(state.capacity());
+        self.cond.notify_all();
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.state.lock().bytes_per_second
+    }
+
+    /// Maximum number of bytes granted at once. Larger requests are split, so callers should
+    /// issue I/O in chunks of at most this size. Unlimited if the rate is not limited.
+    pub fn single_burst_bytes(&self) -> usize {
+        let state = self.state.lock();
+        if state.bytes_per_second == 0 {
+            return usize::MAX;
+        }
+        state.capacity() as usize
+    }
+
+    /// Get the number of bytes granted in a lane so far.
+    pub fn total_bytes(&self, priority: IoPriority) -> u64 {
+        self.state.lock().total_bytes[priority.idx()]
+    }
+
+    /// Block until `bytes` bytes of I/O are granted in the lane of `priority`.
+    pub fn request(&self, bytes: usize, priority: IoPriority) {
+        let mut remaining = bytes as u64;
+        let mut state = self.state.lock();
+        state.waiting[priority.idx()] += 1;
+        while remaining > 0 {
+            if state.bytes_per_second == 0 {
+                state.total_bytes[priority.idx()] += remaining;
+                break;
+            }
+            state.refill();
+            let chunk = remaining.min(state.capacity());
+            let yields_to_flush =
+                priority == IoPriority::Compaction && state.waiting[IoPriority::Flush.idx()] > 0;
+            if !yields_to_flush && state.available >= chunk {
+                state.available -= chunk;
+                state.total_bytes[priority.idx()] += chunk;
+                remaining -= chunk;
+                continue;
+            }
+            // Sleep until the missing tokens are refilled, or the rate or the waiting flushes
+            // change.
+            let missing = if yields_to_flush {
+                state.capacity()
+            } else {
+                chunk - state.available
+            };
+            let timeout = Duration::from_nanos(
+                (missing as u128 * 1_000_000_000 / state.bytes_per_second as u128) as u64,
+            );
+            self.cond.wait_for(&mut state, timeout);
+        }
+        state.waiting[priority.idx()] -= 1;
+        self.cond.notify_all();
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock();
+        f.debug_struct("RateLimiter")
+            .field("bytes_per_second", &state.bytes_per_second)
+            .field("waiting", &state.waiting)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests;
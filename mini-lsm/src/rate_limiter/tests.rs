@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+#[test]
+fn test_unlimited() {
+    let rate_limiter = RateLimiter::new(0);
+    let start = Instant::now();
+    rate_limiter.request(1 << 30, IoPriority::Flush);
+    rate_limiter.request(1 << 30, IoPriority::Compaction);
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), 1 << 30);
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Compaction), 1 << 30);
+}
+
+#[test]
+fn test_single_burst_bytes() {
+    let rate_limiter = RateLimiter::new(0);
+    assert_eq!(rate_limiter.single_burst_bytes(), usize::MAX);
+    rate_limiter.set_bytes_per_second(1_000);
+    assert_eq!(rate_limiter.single_burst_bytes(), 4096);
+    rate_limiter.set_bytes_per_second(1 << 20);
+    assert_eq!(rate_limiter.single_burst_bytes(), (1 << 20) / 10);
+}
+
+#[test]
+fn test_rate() {
+    let rate_limiter = RateLimiter::new(100_000);
+    assert_eq!(rate_limiter.single_burst_bytes(), 10_000);
+    // The bucket starts full, the rest is granted at the rate.
+    let start = Instant::now();
+    rate_limiter.request(10_000, IoPriority::Compaction);
+    assert!(start.elapsed() < Duration::from_millis(50));
+    rate_limiter.request(30_000, IoPriority::Compaction);
+    assert!(start.elapsed() >= Duration::from_millis(280));
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Compaction), 40_000);
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), 0);
+}
+
+#[test]
+fn test_set_bytes_per_second() {
+    let rate_limiter = Arc::new(RateLimiter::new(1_000));
+    rate_limiter.request(100, IoPriority::Flush);
+    let waiter = {
+        let rate_limiter = rate_limiter.clone();
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            // Takes 100 seconds at the initial rate.
+            rate_limiter.request(100_000, IoPriority::Flush);
+            start.elapsed()
+        })
+    };
+    std::thread::sleep(Duration::from_millis(50));
+    rate_limiter.set_bytes_per_second(0);
+    assert_eq!(rate_limiter.bytes_per_second(), 0);
+    assert!(waiter.join().unwrap() < Duration::from_secs(5));
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), 100_100);
+}
+
+#[test]
+fn test_flush_before_compaction() {
+    let rate_limiter = Arc::new(RateLimiter::new(100_000));
+    rate_limiter.request(10_000, IoPriority::Compaction);
+    let spawn = |priority, bytes| {
+        let rate_limiter = rate_limiter.clone();
+        std::thread::spawn(move || {
+            rate_limiter.request(bytes, priority);
+            Instant::now()
+        })
+    };
+    // The compaction needs 400ms on its own, the flush arriving later is served before the
+    // remaining chunks of the compaction.
+    let compaction = spawn(IoPriority::Compaction, 40_000);
+    std::thread::sleep(Duration::from_millis(50));
+    let flush = spawn(IoPriority::Flush, 10_000);
+    let flush_done = flush.join().unwrap();
+    let compaction_done = compaction.join().unwrap();
+    assert!(flush_done < compaction_done);
+    assert!(compaction_done - flush_done >= Duration::from_millis(100));
+}
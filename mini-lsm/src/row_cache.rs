@@ -0,0 +1,93 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use bytes::Bytes;
+use moka::sync::{Cache, ConcurrentCacheExt};
+use parking_lot::Mutex;
+
+const NUM_STRIPES: usize = 64;
+
+/// Caches the results of point lookups by user key, bounded by the total size of the cached keys
+/// and values in bytes. A cached `None` records that the key does not exist.
+///
+/// A lookup that misses the cache reads the LSM tree and then fills the cache. To avoid caching a
+/// value that is overwritten while the lookup is running, keys are hashed into stripes, each with
+/// a version bumped by every write to one of its keys. The lookup takes the version before reading
+/// the tree and only fills the cache if the version has not changed since.
+pub struct RowCache {
+    cache: Cache<Bytes, Option<Bytes>>,
+    stripes: Vec<Mutex<u64>>,
+    hasher: RandomState,
+}
+
+impl RowCache {
+    /// Create a row cache holding up to `capacity` bytes of keys and values.
+    pub fn new(capacity: u64) -> Self {
+        let cache = Cache::builder()
+            .max_capacity(capacity)
+            .weigher(|key: &Bytes, value: &Option<Bytes>| {
+                let size = key.len() + value.as_ref().map_or(0, Bytes::len);
+                size.try_into().unwrap_or(u32::MAX)
+            })
+            .build();
+        Self {
+            cache,
+            stripes: (0..NUM_STRIPES).map(|_| Mutex::new(0)).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn stripe(&self, key: &[u8]) -> &Mutex<u64> {
+        &self.stripes[self.hasher.hash_one(key) as usize % NUM_STRIPES]
+    }
+
+    /// Look up a key. Returns `None` on a miss, and `Some(None)` if the key is cached as deleted.
+    pub fn get(&self, key: &[u8]) -> Option<Option<Bytes>> {
+        self.cache.get(key)
+    }
+
+    /// Get the version of the stripe of a key, to be passed to [`RowCache::fill`].
+    pub fn version(&self, key: &[u8]) -> u64 {
+        *self.stripe(key).lock()
+    }
+
+    /// Cache the result of a lookup, unless the key may have been written since `version` was
+    /// taken. The value is copied, as it may be a slice of a whole block or file mapping, which
+    /// the cache would otherwise keep alive without counting it.
+    pub fn fill(&self, key: &[u8], version: u64, value: Option<Bytes>) {
+        let stripe = self.stripe(key).lock();
+        if *stripe == version {
+            let value = value.map(|value| Bytes::copy_from_slice(&value));
+            self.cache.insert(Bytes::copy_from_slice(key), value);
+        }
+    }
+
+    /// Drop the cached result of a key after it has been written.
+    pub fn invalidate(&self, key: &[u8]) {
+        let mut stripe = self.stripe(key).lock();
+        *stripe += 1;
+        self.cache.invalidate(key);
+    }
+
+    /// Drop all cached results, after keys have been written without going through the cache,
+    /// e.g. by ingesting SSTs.
+    pub fn invalidate_all(&self) {
+        for stripe in &self.stripes {
+            *stripe.lock() += 1;
+        }
+        self.cache.invalidate_all();
+    }
+
+    /// Get the number of cached keys.
+    pub fn entry_count(&self) -> u64 {
+        // Apply pending evictions so that the count is up to date.
+        self.cache.sync();
+        self.cache.entry_count()
+    }
+
+    /// Get the total size of the cached keys and values in bytes.
+    pub fn size(&self) -> u64 {
+        self.cache.sync();
+        self.cache.weighted_size()
+    }
+}
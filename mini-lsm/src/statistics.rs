@@ -0,0 +1,238 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::block_cache::BlockCacheStats;
+use crate::lsm_storage::NUM_LEVELS;
+
+/// Number of buckets of a histogram. Bucket `i` holds the values of `i` significant bits, i.e. the
+/// values in `[2^(i-1), 2^i)`, and bucket 0 holds zero.
+const NUM_BUCKETS: usize = 65;
+
+/// A counter updated with relaxed atomics.
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A histogram with power-of-two buckets, updated with relaxed atomics.
+pub struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: (0..NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, value: u64) {
+        let bucket = (u64::BITS - value.leading_zeros()) as usize;
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// Record the time elapsed since `start` in nanoseconds.
+    pub fn record_since(&self, start: Instant) {
+        self.record(start.elapsed().as_nanos().try_into().unwrap_or(u64::MAX));
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+/// The values recorded by a histogram. Latencies are in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: u64,
+    pub max: u64,
+    /// Number of values in each bucket, the bucket `i` holding the values in `[2^(i-1), 2^i)`.
+    pub buckets: Vec<u64>,
+}
+
+impl HistogramSnapshot {
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.count as f64
+    }
+
+    /// Get an upper bound of the `p`-th percentile, with `p` in `[0, 100]`: the upper end of the
+    /// bucket holding it, capped at the maximum.
+    pub fn percentile(&self, p: f64) -> u64 {
+        let rank = (self.count as f64 * p / 100.0).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let upper = if bucket == 0 {
+                    0
+                } else {
+                    (1u128 << bucket) - 1
+                };
+                return upper.min(self.max as u128) as u64;
+            }
+        }
+        self.max
+    }
+}
+
+/// The counters and histograms of a storage, maintained by its operations.
+#[derive(Default)]
+pub struct Statistics {
+    pub num_gets: Counter,
+    pub get_hits_row_cache: Counter,
+    pub get_hits_memtable: Counter,
+    pub get_hits_sst: Counter,
+    pub get_misses: Counter,
+    pub num_puts: Counter,
+    pub num_deletes: Counter,
+    pub user_bytes_written: Counter,
+    pub num_scans: Counter,
+    pub num_flushes: Counter,
+    pub bytes_flushed: Counter,
+    pub num_compactions: Counter,
+    pub compaction_bytes_read: Counter,
+    pub compaction_bytes_written: Counter,
+    pub bytes_ingested: Counter,
+    /// Bytes written into L0 and each level below it.
+    pub level_bytes_written: [Counter; NUM_LEVELS + 1],
+    pub get_latency: Histogram,
+    pub multi_get_latency: Histogram,
+    pub write_latency: Histogram,
+    pub scan_latency: Histogram,
+    pub flush_latency: Histogram,
+    pub compaction_latency: Histogram,
+}
+
+/// The files of a level and the I/O to them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelStats {
+    pub num_files: usize,
+    /// Total size of the SSTs in bytes.
+    pub size: u64,
+    /// Bytes read from the SSTs currently in the level.
+    pub bytes_read: u64,
+    /// Bytes written into the level by flushes, ingestions and bulk loads.
+    pub bytes_written: u64,
+}
+
+/// A snapshot of the statistics of a storage, returned by
+/// [`LsmStorage::stats`](crate::lsm_storage::LsmStorage::stats). Counters are cumulative since the
+/// storage was opened, latencies are in nanoseconds.
+#[derive(Clone, Debug, Default)]
+pub struct StorageStats {
+    /// Number of keys looked up by `get` and `multi_get`.
+    pub num_gets: u64,
+    /// Lookups served by the row cache.
+    pub get_hits_row_cache: u64,
+    /// Lookups served by the memtables, including tombstones.
+    pub get_hits_memtable: u64,
+    /// Lookups served by the SSTs, including tombstones.
+    pub get_hits_sst: u64,
+    /// Lookups of keys that were never written.
+    pub get_misses: u64,
+    pub num_puts: u64,
+    pub num_deletes: u64,
+    /// Size of the keys and values written by `put` and `delete`.
+    pub user_bytes_written: u64,
+    pub num_scans: u64,
+    pub num_flushes: u64,
+    /// Size of the SSTs and blob files written by flushes.
+    pub bytes_flushed: u64,
+    pub num_compactions: u64,
+    pub compaction_bytes_read: u64,
+    pub compaction_bytes_written: u64,
+    /// Size of the SSTs added by `ingest_files` and `bulk_load`.
+    pub bytes_ingested: u64,
+    /// Bytes written by flushes and compactions per byte written by the user, 0 before the first
+    /// write.
+    pub write_amplification: f64,
+    pub block_cache: BlockCacheStats,
+    /// L0 followed by the levels below it.
+    pub levels: Vec<LevelStats>,
+    pub get_latency: HistogramSnapshot,
+    pub multi_get_latency: HistogramSnapshot,
+    /// Latency of `put` and `delete`.
+    pub write_latency: HistogramSnapshot,
+    /// Latency of creating a scan iterator.
+    pub scan_latency: HistogramSnapshot,
+    pub flush_latency: HistogramSnapshot,
+    pub compaction_latency: HistogramSnapshot,
+}
+
+impl Statistics {
+    /// Take a snapshot of the counters. The block cache and level statistics are left to the
+    /// caller.
+    pub fn snapshot(&self) -> StorageStats {
+        let user_bytes_written = self.user_bytes_written.get();
+        let bytes_written = self.bytes_flushed.get() + self.compaction_bytes_written.get();
+        StorageStats {
+            num_gets: self.num_gets.get(),
+            get_hits_row_cache: self.get_hits_row_cache.get(),
+            get_hits_memtable: self.get_hits_memtable.get(),
+            get_hits_sst: self.get_hits_sst.get(),
+            get_misses: self.get_misses.get(),
+            num_puts: self.num_puts.get(),
+            num_deletes: self.num_deletes.get(),
+            user_bytes_written,
+            num_scans: self.num_scans.get(),
+            num_flushes: self.num_flushes.get(),
+            bytes_flushed: self.bytes_flushed.get(),
+            num_compactions: self.num_compactions.get(),
+            compaction_bytes_read: self.compaction_bytes_read.get(),
+            compaction_bytes_written: self.compaction_bytes_written.get(),
+            bytes_ingested: self.bytes_ingested.get(),
+            write_amplification: if user_bytes_written == 0 {
+                0.0
+            } else {
+                bytes_written as f64 / user_bytes_written as f64
+            },
+            block_cache: BlockCacheStats::default(),
+            levels: Vec::new(),
+            get_latency: self.get_latency.snapshot(),
+            multi_get_latency: self.multi_get_latency.snapshot(),
+            write_latency: self.write_latency.snapshot(),
+            scan_latency: self.scan_latency.snapshot(),
+            flush_latency: self.flush_latency.snapshot(),
+            compaction_latency: self.compaction_latency.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,54 @@
+use super::*;
+
+#[test]
+fn test_histogram() {
+    let histogram = Histogram::default();
+    assert_eq!(histogram.snapshot().percentile(50.0), 0);
+    assert_eq!(histogram.snapshot().mean(), 0.0);
+    for value in [0, 1, 2, 3, 100, 1000, 1000, 1000, 5000, u64::MAX / 2] {
+        histogram.record(value);
+    }
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.count, 10);
+    assert_eq!(snapshot.max, u64::MAX / 2);
+    assert_eq!(snapshot.buckets.len(), NUM_BUCKETS);
+    assert_eq!(snapshot.buckets[0], 1);
+    assert_eq!(snapshot.buckets[1], 1);
+    assert_eq!(snapshot.buckets[2], 2);
+    // 1000 has 10 significant bits.
+    assert_eq!(snapshot.buckets[10], 3);
+    assert_eq!(snapshot.buckets[63], 1);
+    assert_eq!(snapshot.buckets.iter().sum::<u64>(), 10);
+
+    assert_eq!(snapshot.percentile(0.0), 0);
+    assert_eq!(snapshot.percentile(10.0), 0);
+    assert_eq!(snapshot.percentile(40.0), 3);
+    assert_eq!(snapshot.percentile(50.0), 127);
+    assert_eq!(snapshot.percentile(80.0), 1023);
+    assert_eq!(snapshot.percentile(90.0), 8191);
+    assert_eq!(snapshot.percentile(100.0), u64::MAX / 2);
+}
+
+#[test]
+fn test_histogram_mean() {
+    let histogram = Histogram::default();
+    for value in [10, 20, 30, 40] {
+        histogram.record(value);
+    }
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.sum, 100);
+    assert_eq!(snapshot.mean(), 25.0);
+    // Capped at the maximum.
+    assert_eq!(snapshot.percentile(100.0), 40);
+}
+
+#[test]
+fn test_statistics_write_amplification() {
+    let stats = Statistics::default();
+    assert_eq!(stats.snapshot().write_amplification, 0.0);
+    stats.user_bytes_written.add(100);
+    stats.bytes_flushed.add(150);
+    stats.compaction_bytes_written.add(250);
+    stats.bytes_ingested.add(1000);
+    assert_eq!(stats.snapshot().write_amplification, 4.0);
+}
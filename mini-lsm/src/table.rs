@@ -1,17 +1,30 @@
+mod bloom;
 mod builder;
+mod index;
 mod iterator;
+mod properties;
+mod sst_file;
 
-use std::fs::File;
+use std::ops::Bound;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
+pub(crate) use bloom::PrefixFilterBuilder;
+pub use bloom::{Bloom, PrefixFilter};
 pub use builder::SsTableBuilder;
 use bytes::{Buf, BufMut, Bytes};
+pub use index::{BlockHandle, BlockIndex, IndexPartition};
 pub use iterator::SsTableIterator;
+pub use properties::TableProperties;
+pub use sst_file::{SstFileIterator, SstFileReader, SstFileWriter};
 
-use crate::block::Block;
-use crate::lsm_storage::BlockCache;
+use crate::block::{Block, BlockIterator};
+use crate::block_cache::BlockCache;
+use crate::env::{write_file, Env, RandomAccessFile};
+use crate::prefix_extractor::{prefix_upper_bound, PrefixExtractor};
+use crate::rate_limiter::{IoPriority, RateLimiter};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
@@ -45,19 +58,25 @@ impl BlockMeta {
     }
 
     /// Decode block meta from a buffer.
-    pub fn decode_block_meta(mut buf: impl Buf) -> Vec<BlockMeta> {
+    pub fn decode_block_meta(mut buf: impl Buf) -> Result<Vec<BlockMeta>> {
         let mut block_meta = Vec::new();
         while buf.has_remaining() {
+            if buf.remaining() < std::mem::size_of::<u32>() + std::mem::size_of::<u16>() {
+                bail!("block meta is truncated");
+            }
             let offset = buf.get_u32() as usize;
             let first_key_len = buf.get_u16() as usize;
+            if buf.remaining() < first_key_len {
+                bail!("block meta is truncated");
+            }
             let first_key = buf.copy_to_bytes(first_key_len);
             block_meta.push(BlockMeta { offset, first_key });
         }
-        block_meta
+        Ok(block_meta)
     }
 }
 
-/// A file object.
+/// A file object. The bytes read from the file are counted for the statistics of the storage.
 ///
 /// Before day 4, it should look like:
 ///
@@ -65,8 +84,8 @@ impl BlockMeta {
 /// pub struct FileObject(Bytes);
 ///
 /// impl FileObject {
-///     pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-///         Ok(self.0[offset as usize..(offset + len) as usize].to_vec())
+///     pub fn read(&self, offset: u64, len: u64) -> Result<Bytes> {
+///         Ok(self.0.slice(offset as usize..(offset + len) as usize))
 ///    }
 ///     pub fn size(&self) -> u64 {
 ///         self.0.len() as u64
@@ -81,40 +100,94 @@ impl BlockMeta {
 ///     }
 /// }
 /// ```
-pub struct FileObject(File, u64);
+pub struct FileObject(Box<dyn RandomAccessFile>, Arc<AtomicU64>);
 
 impl FileObject {
-    pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-        use std::os::unix::fs::FileExt;
-        let mut data = vec![0; len as usize];
-        self.0.read_exact_at(&mut data[..], offset)?;
+    pub fn read(&self, offset: u64, len: u64) -> Result<Bytes> {
+        let data = self.0.read_at(offset, len)?;
+        self.1.fetch_add(data.len() as u64, Ordering::Relaxed);
         Ok(data)
     }
 
     pub fn size(&self) -> u64 {
-        self.1
+        self.0.size()
+    }
+
+    /// Check if reads are served from memory without any I/O, e.g. for memory-mapped files.
+    pub fn is_in_memory(&self) -> bool {
+        self.0.is_in_memory()
+    }
+
+    /// Get the counter of the bytes read from the file.
+    pub(crate) fn read_counter(&self) -> Arc<AtomicU64> {
+        self.1.clone()
+    }
+
+    /// Count the bytes read into an existing counter, e.g. the one of an earlier handle of the
+    /// same file.
+    pub(crate) fn with_read_counter(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.1 = counter;
+        self
     }
 
     /// Create a new file object (day 2) and write the file to the disk (day 4).
-    pub fn create(path: &Path, data: Vec<u8>) -> Result<Self> {
-        std::fs::write(path, &data)?;
-        Ok(FileObject(
-            File::options().read(true).write(false).open(path)?,
-            data.len() as u64,
-        ))
+    pub fn create(env: &dyn Env, path: &Path, data: Vec<u8>) -> Result<Self> {
+        write_file(env, path, &data)?;
+        Self::open(env, path)
+    }
+
+    /// Create a new file object and write the file in chunks granted by a rate limiter in the lane
+    /// of `priority`.
+    pub fn create_with_rate_limiter(
+        env: &dyn Env,
+        path: &Path,
+        data: Vec<u8>,
+        rate_limiter: &RateLimiter,
+        priority: IoPriority,
+    ) -> Result<Self> {
+        let mut file = env.create(path)?;
+        for chunk in data.chunks(rate_limiter.single_burst_bytes()) {
+            rate_limiter.request(chunk.len(), priority);
+            file.write(chunk)?;
+        }
+        file.sync()?;
+        Self::open(env, path)
+    }
+
+    /// Open an existing file for reading.
+    pub fn open(env: &dyn Env, path: &Path) -> Result<Self> {
+        Ok(FileObject(env.open(path)?, Arc::default()))
     }
 
-    pub fn open(_path: &Path) -> Result<Self> {
-        unimplemented!()
+    /// Open an existing file and map it into memory. Files larger than `max_mmap_size` bytes are
+    /// read with `pread` instead, to bound the address space taken by the mappings.
+    pub fn open_mmap(env: &dyn Env, path: &Path, max_mmap_size: u64) -> Result<Self> {
+        Ok(FileObject(
+            env.open_mmap(path, max_mmap_size)?,
+            Arc::default(),
+        ))
     }
 }
 
+/// An SST file has the following layout:
+///
+/// ```text
+/// | data blocks | index partitions | index | prefix filter | properties | footer |
+/// ```
+///
+/// where the footer holds the offsets of the index, the prefix filter and the properties, each as a
+/// u32. With a flat index, there are no index partitions and the index is the list of all block
+/// metas. With a partitioned index, the block metas are split into index partitions, each encoded
+/// as a block, and the index is a small top-level index over the partitions. The prefix filter is
+/// empty if the table was built without a prefix extractor.
 pub struct SsTable {
     file: FileObject,
-    block_metas: Vec<BlockMeta>,
+    index: BlockIndex,
     block_meta_offset: usize,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
+    properties: TableProperties,
+    prefix_filter: Option<Arc<PrefixFilter>>,
 }
 
 impl SsTable {
@@ -126,53 +199,409 @@ impl SsTable {
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
         let len = file.size();
-        let raw_meta_offset = file.read(len - 4, 4)?;
-        let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
-        let raw_meta = file.read(block_meta_offset, len - 4 - block_meta_offset)?;
+        if len < 12 {
+            bail!("SST of {} bytes is too small", len);
+        }
+        let mut raw_footer = &file.read(len - 12, 12)?[..];
+        let block_meta_offset = raw_footer.get_u32() as u64;
+        let prefix_filter_offset = raw_footer.get_u32() as u64;
+        let properties_offset = raw_footer.get_u32() as u64;
+        if block_meta_offset > prefix_filter_offset
+            || prefix_filter_offset > properties_offset
+            || properties_offset > len - 12
+        {
+            bail!("SST footer is corrupted");
+        }
+        let raw_meta = file.read(block_meta_offset, prefix_filter_offset - block_meta_offset)?;
+        let prefix_filter = if prefix_filter_offset < properties_offset {
+            let raw_filter = file.read(
+                prefix_filter_offset,
+                properties_offset - prefix_filter_offset,
+            )?;
+            Some(Arc::new(PrefixFilter::decode(&raw_filter[..])?))
+        } else {
+            None
+        };
+        let raw_properties = file.read(properties_offset, len - 12 - properties_offset)?;
+        let properties = TableProperties::decode(&raw_properties[..])?;
+        let index = if properties.num_index_partitions == 0 {
+            let block_metas = BlockMeta::decode_block_meta(&raw_meta[..])?;
+            let offsets_increasing = block_metas
+                .windows(2)
+                .all(|pair| pair[0].offset < pair[1].offset);
+            if block_metas.is_empty()
+                || !offsets_increasing
+                || block_metas.last().unwrap().offset >= block_meta_offset as usize
+            {
+                bail!("SST block meta is corrupted");
+            }
+            BlockIndex::Flat(block_metas)
+        } else {
+            let (partitions, num_of_blocks) =
+                IndexPartition::decode_top_level_index(&raw_meta[..])?;
+            let partitions_valid = partitions.first().is_some_and(|x| x.first_block_idx == 0)
+                && partitions
+                    .windows(2)
+                    .all(|pair| pair[0].first_block_idx < pair[1].first_block_idx)
+                && partitions.last().unwrap().first_block_idx < num_of_blocks
+                && partitions
+                    .iter()
+                    .all(|x| x.offset + x.len <= block_meta_offset as usize);
+            if !partitions_valid {
+                bail!("SST index is corrupted");
+            }
+            BlockIndex::Partitioned {
+                partitions,
+                num_of_blocks,
+            }
+        };
         Ok(Self {
             file,
-            block_metas: BlockMeta::decode_block_meta(&raw_meta[..]),
+            index,
             block_meta_offset: block_meta_offset as usize,
             id,
             block_cache,
+            properties,
+            prefix_filter,
         })
     }
 
+    /// Get the offset and length of a data block.
+    fn block_range(&self, block_idx: usize) -> Result<(usize, usize)> {
+        match &self.index {
+            BlockIndex::Flat(block_metas) => {
+                let offset = block_metas[block_idx].offset;
+                let offset_end = block_metas
+                    .get(block_idx + 1)
+                    .map_or(self.block_meta_offset, |x| x.offset);
+                Ok((offset, offset_end - offset))
+            }
+            BlockIndex::Partitioned { partitions, .. } => {
+                let partition_idx = partitions
+                    .partition_point(|partition| partition.first_block_idx <= block_idx)
+                    - 1;
+                let mut iter = BlockIterator::create_and_seek_to_first(
+                    self.read_index_partition_cached(partition_idx)?,
+                );
+                iter.seek_to(block_idx - partitions[partition_idx].first_block_idx);
+                if !iter.is_valid() {
+                    bail!("block {} is missing from the index", block_idx);
+                }
+                let handle = BlockHandle::decode(iter.value())?;
+                if handle.block_idx != block_idx
+                    || handle.offset + handle.len > self.block_meta_offset
+                {
+                    bail!("index entry of block {} is corrupted", block_idx);
+                }
+                Ok((handle.offset, handle.len))
+            }
+        }
+    }
+
     /// Read a block from the disk.
     pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self.block_metas[block_idx].offset;
-        let offset_end = self
-            .block_metas
-            .get(block_idx + 1)
-            .map_or(self.block_meta_offset, |x| x.offset);
-        let block_data = self
+        let (offset, len) = self.block_range(block_idx)?;
+        let block_data = self.file.read(offset as u64, len as u64)?;
+        Ok(Arc::new(Block::decode(block_data)?))
+    }
+
+    /// Read `count` consecutive blocks starting at `block_idx` with a single read, bypassing the
+    /// block cache.
+    pub fn read_blocks(&self, block_idx: usize, count: usize) -> Result<Vec<Arc<Block>>> {
+        let ranges = (block_idx..block_idx + count)
+            .map(|idx| self.block_range(idx))
+            .collect::<Result<Vec<_>>>()?;
+        let (Some(&(start, _)), Some(&(last_offset, last_len))) = (ranges.first(), ranges.last())
+        else {
+            return Ok(Vec::new());
+        };
+        if ranges
+            .windows(2)
+            .any(|pair| pair[0].0 + pair[0].1 != pair[1].0)
+        {
+            bail!(
+                "blocks {}..{} are not contiguous",
+                block_idx,
+                block_idx + count
+            );
+        }
+        let data = self
             .file
-            .read(offset as u64, (offset_end - offset) as u64)?;
-        Ok(Arc::new(Block::decode(&block_data[..])))
+            .read(start as u64, (last_offset + last_len - start) as u64)?;
+        ranges
+            .into_iter()
+            .map(|(offset, len)| {
+                Ok(Arc::new(Block::decode(
+                    data.slice(offset - start..offset - start + len),
+                )?))
+            })
+            .collect()
+    }
+
+    /// Check if a block is in the block cache.
+    pub fn is_block_cached(&self, block_idx: usize) -> bool {
+        self.block_cache
+            .as_ref()
+            .is_some_and(|block_cache| block_cache.contains_key(&(self.id, block_idx)))
+    }
+
+    /// Check if the table file is read from memory, e.g. through a memory mapping.
+    pub fn is_in_memory(&self) -> bool {
+        self.file.is_in_memory()
     }
 
     /// Read a block from disk, with block cache.
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
         if let Some(ref block_cache) = self.block_cache {
-            let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
-            Ok(blk)
+            block_cache.try_get_with((self.id, block_idx), || self.read_block(block_idx))
         } else {
             self.read_block(block_idx)
         }
     }
 
+    /// Read an index partition, with block cache. Index partitions share the block cache with data
+    /// blocks and are keyed after the last data block.
+    fn read_index_partition_cached(&self, partition_idx: usize) -> Result<Arc<Block>> {
+        let BlockIndex::Partitioned {
+            partitions,
+            num_of_blocks,
+        } = &self.index
+        else {
+            unreachable!("the table does not have a partitioned index");
+        };
+        let partition = &partitions[partition_idx];
+        let read_partition = || -> Result<Arc<Block>> {
+            let data = self
+                .file
+                .read(partition.offset as u64, partition.len as u64)?;
+            Ok(Arc::new(Block::decode(data)?))
+        };
+        if let Some(ref block_cache) = self.block_cache {
+            block_cache.try_get_with((self.id, num_of_blocks + partition_idx), read_partition)
+        } else {
+            read_partition()
+        }
+    }
+
     /// Find the block that may contain `key`.
-    pub fn find_block_idx(&self, key: &[u8]) -> usize {
-        self.block_metas
-            .partition_point(|meta| meta.first_key <= key)
-            .saturating_sub(1)
+    pub fn find_block_idx(&self, key: &[u8]) -> Result<usize> {
+        match &self.index {
+            BlockIndex::Flat(block_metas) => Ok(block_metas
+                .partition_point(|meta| meta.first_key <= key)
+                .saturating_sub(1)),
+            BlockIndex::Partitioned {
+                partitions,
+                num_of_blocks,
+            } => {
+                let partition_idx = partitions
+                    .partition_point(|partition| partition.first_key <= key)
+                    .saturating_sub(1);
+                let iter = BlockIterator::create_and_seek_to_key(
+                    self.read_index_partition_cached(partition_idx)?,
+                    key,
+                );
+                if !iter.is_valid() {
+                    // All blocks in the partition start before `key`, pick the last one.
+                    return Ok(partitions
+                        .get(partition_idx + 1)
+                        .map_or(*num_of_blocks, |next| next.first_block_idx)
+                        - 1);
+                }
+                let handle = BlockHandle::decode(iter.value())?;
+                if iter.key() == key {
+                    Ok(handle.block_idx)
+                } else {
+                    Ok(handle.block_idx.saturating_sub(1))
+                }
+            }
+        }
+    }
+
+    /// Get the total size of the data blocks in bytes.
+    pub fn data_size(&self) -> Result<u64> {
+        let (offset, len) = self.block_range(self.num_of_blocks() - 1)?;
+        Ok((offset + len) as u64)
+    }
+
+    /// Get the approximate offset of `key` in the data blocks: the offset of the block that may
+    /// contain it, zero before the first key, and the end of the data blocks after the last key.
+    pub fn approximate_offset_of(&self, key: &[u8]) -> Result<u64> {
+        if key <= self.first_key().as_ref() {
+            return Ok(0);
+        }
+        if key > self.last_key().as_ref() {
+            return self.data_size();
+        }
+        let (offset, _) = self.block_range(self.find_block_idx(key)?)?;
+        Ok(offset as u64)
+    }
+
+    /// Look up a key in the table. Returns the value as it is stored, with tombstones as empty
+    /// values.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        if !self.may_contain_key(key) {
+            return Ok(None);
+        }
+        let block_idx = self.find_block_idx(key)?;
+        let mut iter = BlockIterator::create_and_seek_to_first(self.read_block_cached(block_idx)?);
+        if iter.seek_to_exact_key(key) {
+            return Ok(Some(iter.value_bytes()));
+        }
+        Ok(None)
+    }
+
+    /// Look up a batch of keys, sorted in ascending order, reading each block at most once.
+    /// Returns the values as `get` does, in the order of `keys`.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        debug_assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        let mut values = Vec::with_capacity(keys.len());
+        // The block of the previous key. Sorted keys falling into the same block are adjacent.
+        let mut current: Option<(usize, BlockIterator)> = None;
+        for key in keys {
+            if !self.may_contain_key(key) {
+                values.push(None);
+                continue;
+            }
+            let block_idx = self.find_block_idx(key)?;
+            if current.as_ref().map(|(idx, _)| *idx) != Some(block_idx) {
+                let block = self.read_block_cached(block_idx)?;
+                current = Some((block_idx, BlockIterator::create_and_seek_to_first(block)));
+            }
+            let (_, iter) = current.as_mut().unwrap();
+            values.push(iter.seek_to_exact_key(key).then(|| iter.value_bytes()));
+        }
+        Ok(values)
     }
 
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
-        self.block_metas.len()
+        match &self.index {
+            BlockIndex::Flat(block_metas) => block_metas.len(),
+            BlockIndex::Partitioned { num_of_blocks, .. } => *num_of_blocks,
+        }
+    }
+
+    /// Get the id of the table.
+    pub fn sst_id(&self) -> usize {
+        self.id
+    }
+
+    /// Get the table-level statistics.
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Get the smallest key in the table.
+    pub fn first_key(&self) -> &Bytes {
+        &self.properties.first_key
+    }
+
+    /// Get the largest key in the table.
+    pub fn last_key(&self) -> &Bytes {
+        &self.properties.last_key
+    }
+
+    /// Check if `key` falls in the key range of the table.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        self.properties.may_contain_key(key)
+    }
+
+    /// Check if the key range of the table intersects with the given range.
+    pub fn range_overlap(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+        self.properties.range_overlap(lower, upper)
+    }
+
+    /// Get the prefix bloom filter of the table, if it was built with a prefix extractor.
+    pub fn prefix_filter(&self) -> Option<&PrefixFilter> {
+        self.prefix_filter.as_deref()
+    }
+}
+
+/// What the LSM tree keeps in memory for an SST, so that the SST can be pruned without being open.
+/// The [`SsTable`] itself is opened on demand through the table cache.
+#[derive(Clone, Debug)]
+pub struct SsTableMeta {
+    id: usize,
+    file_size: u64,
+    properties: TableProperties,
+    prefix_filter: Option<Arc<PrefixFilter>>,
+    /// Bytes read from the table, shared by all the times the table is opened.
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl SsTableMeta {
+    /// Collect the metadata of an open table.
+    pub fn new(table: &SsTable) -> Self {
+        Self {
+            id: table.id,
+            file_size: table.file.size(),
+            properties: table.properties.clone(),
+            prefix_filter: table.prefix_filter.clone(),
+            bytes_read: table.file.read_counter(),
+        }
+    }
+
+    /// Get the id of the table.
+    pub fn sst_id(&self) -> usize {
+        self.id
+    }
+
+    /// Get the size of the table file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Get the number of bytes read from the table file since it was added to the storage.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Get the counter of the bytes read, to be passed to the file when the table is reopened.
+    pub(crate) fn read_counter(&self) -> Arc<AtomicU64> {
+        self.bytes_read.clone()
+    }
+
+    /// Get the table-level statistics.
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Get the smallest key in the table.
+    pub fn first_key(&self) -> &Bytes {
+        &self.properties.first_key
+    }
+
+    /// Get the largest key in the table.
+    pub fn last_key(&self) -> &Bytes {
+        &self.properties.last_key
+    }
+
+    /// Check if `key` falls in the key range of the table.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        self.properties.may_contain_key(key)
+    }
+
+    /// Check if the key range of the table intersects with the given range.
+    pub fn range_overlap(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+        self.properties.range_overlap(lower, upper)
+    }
+
+    /// Check if the table may contain keys starting with `prefix`, from its key range and, if
+    /// there is one, its prefix bloom filter.
+    pub fn may_contain_prefix(
+        &self,
+        extractor: Option<&dyn PrefixExtractor>,
+        prefix: &[u8],
+    ) -> bool {
+        let upper = prefix_upper_bound(prefix);
+        let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        if !self.range_overlap(Bound::Included(prefix), upper) {
+            return false;
+        }
+        match (&self.prefix_filter, extractor) {
+            (Some(filter), Some(extractor)) => filter.may_contain(extractor, prefix),
+            _ => true,
+        }
     }
 }
 
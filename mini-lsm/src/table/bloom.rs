@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::prefix_extractor::PrefixExtractor;
+
+/// A bloom filter over 32-bit hashes. As in LevelDB, each hash sets `k` bits, derived from the
+/// hash by double hashing.
+///
+/// ```text
+/// | bits | k (u8) |
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bloom {
+    filter: Bytes,
+    k: u8,
+}
+
+impl Bloom {
+    /// Hash a key for the filter. The hash is part of the on-disk format, so it must not depend on
+    /// the platform or the Rust version.
+    pub fn hash(key: &[u8]) -> u32 {
+        crc32fast::hash(key)
+    }
+
+    /// Build a filter holding the given hashes, with `bits_per_key` bits for each of them.
+    pub fn build_from_hashes(hashes: &[u32], bits_per_key: usize) -> Self {
+        // ln(2) * bits per key minimizes the false positive rate.
+        let k = ((bits_per_key as f64 * 0.69) as u8).clamp(1, 30);
+        let num_bits = (hashes.len() * bits_per_key).max(64);
+        let num_bytes = num_bits.div_ceil(8);
+        let num_bits = num_bytes * 8;
+        let mut filter = vec![0u8; num_bytes];
+        for hash in hashes {
+            let mut hash = *hash;
+            let delta = hash.rotate_left(15);
+            for _ in 0..k {
+                let bit = hash as usize % num_bits;
+                filter[bit / 8] |= 1 << (bit % 8);
+                hash = hash.wrapping_add(delta);
+            }
+        }
+        Self {
+            filter: filter.into(),
+            k,
+        }
+    }
+
+    /// Check if a hash may have been added to the filter.
+    pub fn may_contain(&self, hash: u32) -> bool {
+        let num_bits = self.filter.len() * 8;
+        let mut hash = hash;
+        let delta = hash.rotate_left(15);
+        for _ in 0..self.k {
+            let bit = hash as usize % num_bits;
+            if self.filter[bit / 8] & (1 << (bit % 8)) == 0 {
+                return false;
+            }
+            hash = hash.wrapping_add(delta);
+        }
+        true
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_slice(&self.filter);
+        buf.put_u8(self.k);
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let Some((k, filter)) = data.split_last() else {
+            bail!("bloom filter truncated");
+        };
+        if filter.is_empty() {
+            bail!("bloom filter truncated");
+        }
+        Ok(Self {
+            filter: Bytes::copy_from_slice(filter),
+            k: *k,
+        })
+    }
+}
+
+/// The prefix bloom filter of an SST, holding the prefixes of its keys. It records the name of the
+/// extractor the prefixes were taken with, and is only used with the same extractor.
+///
+/// ```text
+/// | extractor name length (u16) | extractor name | bloom filter |
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixFilter {
+    extractor: String,
+    bloom: Bloom,
+}
+
+impl PrefixFilter {
+    /// Check if the table may contain keys starting with `prefix`.
+    pub fn may_contain(&self, extractor: &dyn PrefixExtractor, prefix: &[u8]) -> bool {
+        // Keys starting with a prefix that is out of the domain may have different prefixes.
+        if extractor.name() != self.extractor || !extractor.in_domain(prefix) {
+            return true;
+        }
+        self.bloom
+            .may_contain(Bloom::hash(extractor.transform(prefix)))
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u16(self.extractor.len() as u16);
+        buf.put_slice(self.extractor.as_bytes());
+        self.bloom.encode(buf);
+    }
+
+    pub fn decode(mut data: &[u8]) -> Result<Self> {
+        if data.remaining() < 2 {
+            bail!("prefix filter truncated");
+        }
+        let name_len = data.get_u16() as usize;
+        if data.remaining() < name_len {
+            bail!("prefix filter truncated");
+        }
+        let extractor = String::from_utf8(data[..name_len].to_vec())?;
+        let bloom = Bloom::decode(&data[name_len..])?;
+        Ok(Self { extractor, bloom })
+    }
+}
+
+/// Collects the prefixes of the keys added to an SST.
+pub(crate) struct PrefixFilterBuilder {
+    extractor: Arc<dyn PrefixExtractor>,
+    bits_per_key: usize,
+    hashes: Vec<u32>,
+    last_prefix: Option<Vec<u8>>,
+}
+
+impl PrefixFilterBuilder {
+    pub fn new(extractor: Arc<dyn PrefixExtractor>, bits_per_key: usize) -> Self {
+        Self {
+            extractor,
+            bits_per_key,
+            hashes: Vec::new(),
+            last_prefix: None,
+        }
+    }
+
+    /// Add a key. Keys are added in order, so the keys of a prefix are adjacent.
+    pub fn add(&mut self, key: &[u8]) {
+        if !self.extractor.in_domain(key) {
+            return;
+        }
+        let prefix = self.extractor.transform(key);
+        if self.last_prefix.as_deref() != Some(prefix) {
+            self.hashes.push(Bloom::hash(prefix));
+            self.last_prefix = Some(prefix.to_vec());
+        }
+    }
+
+    pub fn build(self) -> PrefixFilter {
+        PrefixFilter {
+            extractor: self.extractor.name().to_string(),
+            bloom: Bloom::build_from_hashes(&self.hashes, self.bits_per_key),
+        }
+    }
+}
@@ -1,12 +1,19 @@
 use std::path::Path;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bytes::BufMut;
 
-use super::{BlockMeta, FileObject, SsTable};
+use super::{
+    BlockIndex, BlockMeta, FileObject, IndexPartition, PrefixFilterBuilder, SsTable,
+    TableProperties,
+};
 use crate::block::BlockBuilder;
-use crate::lsm_storage::BlockCache;
+use crate::block_cache::BlockCache;
+use crate::env::{Env, PosixEnv};
+use crate::prefix_extractor::PrefixExtractor;
+use crate::rate_limiter::{IoPriority, RateLimiter};
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
@@ -15,6 +22,16 @@ pub struct SsTableBuilder {
     data: Vec<u8>,
     pub(super) meta: Vec<BlockMeta>,
     block_size: usize,
+    last_key: Vec<u8>,
+    properties: TableProperties,
+    /// Target size of the index partitions, `None` for a flat index.
+    index_partition_size: Option<usize>,
+    /// Whether the data blocks have a hash index.
+    block_hash_index: bool,
+    /// Collects the key prefixes for the prefix bloom filter, if there is one.
+    prefix_filter: Option<PrefixFilterBuilder>,
+    /// Limits the rate at which the file is written, if set.
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
 }
 
 impl SsTableBuilder {
@@ -26,14 +43,75 @@ impl SsTableBuilder {
             first_key: Vec::new(),
             block_size,
             builder: BlockBuilder::new(block_size),
+            last_key: Vec::new(),
+            properties: TableProperties::default(),
+            index_partition_size: None,
+            block_hash_index: false,
+            prefix_filter: None,
+            rate_limiter: None,
+        }
+    }
+
+    /// Write the file in chunks granted by a rate limiter in the lane of `priority`.
+    pub fn with_rate_limiter(
+        mut self,
+        rate_limiter: Arc<RateLimiter>,
+        priority: IoPriority,
+    ) -> Self {
+        self.rate_limiter = Some((rate_limiter, priority));
+        self
+    }
+
+    /// Write a partitioned index, where the block metas are split into index partitions of the
+    /// given target size.
+    pub fn with_partitioned_index(mut self, index_partition_size: usize) -> Self {
+        self.index_partition_size = Some(index_partition_size);
+        self
+    }
+
+    /// Append a hash index to every data block for faster point lookups.
+    pub fn with_block_hash_index(mut self) -> Self {
+        self.block_hash_index = true;
+        self.builder = self.new_block_builder();
+        self
+    }
+
+    /// Write a bloom filter of the key prefixes taken by `extractor`, with `bits_per_key` bits for
+    /// each distinct prefix.
+    pub fn with_prefix_bloom(
+        mut self,
+        extractor: Arc<dyn PrefixExtractor>,
+        bits_per_key: usize,
+    ) -> Self {
+        self.prefix_filter = Some(PrefixFilterBuilder::new(extractor, bits_per_key));
+        self
+    }
+
+    fn new_block_builder(&self) -> BlockBuilder {
+        if self.block_hash_index {
+            BlockBuilder::new_with_hash_index(self.block_size)
+        } else {
+            BlockBuilder::new(self.block_size)
         }
     }
 
     /// Adds a key-value pair to SSTable
     pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        self.add_with_value_size(key, value, value.len());
+    }
+
+    /// Adds a key-value pair whose stored `value` encodes a user value of `value_size` bytes,
+    /// which is what the table properties count.
+    pub(crate) fn add_with_value_size(&mut self, key: &[u8], value: &[u8], value_size: usize) {
         if self.first_key.is_empty() {
             self.first_key = key.to_vec();
         }
+        self.properties.add(key, value_size);
+        if let Some(ref mut prefix_filter) = self.prefix_filter {
+            prefix_filter.add(key);
+        }
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
 
         if self.builder.add(key, value) {
             return;
@@ -52,7 +130,8 @@ impl SsTableBuilder {
     }
 
     fn finish_block(&mut self) {
-        let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
+        let new_builder = self.new_block_builder();
+        let builder = std::mem::replace(&mut self.builder, new_builder);
         let encoded_block = builder.build().encode();
         self.meta.push(BlockMeta {
             offset: self.data.len(),
@@ -64,23 +143,86 @@ impl SsTableBuilder {
     /// Builds the SSTable and writes it to the given path. No need to actually write to disk until
     /// chapter 4 block cache.
     pub fn build(
+        self,
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        path: impl AsRef<Path>,
+    ) -> Result<SsTable> {
+        self.build_with_env(id, block_cache, &PosixEnv, path)
+    }
+
+    /// Builds the SSTable and writes it to the given path of an [`Env`].
+    pub fn build_with_env(
         mut self,
         id: usize,
         block_cache: Option<Arc<BlockCache>>,
+        env: &dyn Env,
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
+        if self.properties.num_entries == 0 {
+            bail!("cannot build an empty SST");
+        }
         self.finish_block();
         let mut buf = self.data;
+        let mut properties = self.properties;
+        properties.first_key = self.meta[0].first_key.clone();
+        properties.last_key = self.last_key.into();
+        properties.creation_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let index = match self.index_partition_size {
+            Some(partition_size) => {
+                let data_end = buf.len();
+                let partitions = IndexPartition::build_partitions(
+                    &self.meta,
+                    data_end,
+                    partition_size,
+                    &mut buf,
+                );
+                properties.num_index_partitions = partitions.len() as u64;
+                BlockIndex::Partitioned {
+                    partitions,
+                    num_of_blocks: self.meta.len(),
+                }
+            }
+            None => BlockIndex::Flat(self.meta),
+        };
         let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, &mut buf);
+        match &index {
+            BlockIndex::Flat(block_metas) => BlockMeta::encode_block_meta(block_metas, &mut buf),
+            BlockIndex::Partitioned {
+                partitions,
+                num_of_blocks,
+            } => IndexPartition::encode_top_level_index(partitions, *num_of_blocks, &mut buf),
+        }
+        let prefix_filter_offset = buf.len();
+        let prefix_filter = self.prefix_filter.map(|builder| Arc::new(builder.build()));
+        if let Some(ref prefix_filter) = prefix_filter {
+            prefix_filter.encode(&mut buf);
+        }
+        let properties_offset = buf.len();
+        properties.encode(&mut buf);
         buf.put_u32(meta_offset as u32);
-        let file = FileObject::create(path.as_ref(), buf)?;
+        buf.put_u32(prefix_filter_offset as u32);
+        buf.put_u32(properties_offset as u32);
+        let file = match self.rate_limiter {
+            Some((rate_limiter, priority)) => FileObject::create_with_rate_limiter(
+                env,
+                path.as_ref(),
+                buf,
+                &rate_limiter,
+                priority,
+            )?,
+            None => FileObject::create(env, path.as_ref(), buf)?,
+        };
         Ok(SsTable {
             id,
             file,
-            block_metas: self.meta,
+            index,
             block_meta_offset: meta_offset,
             block_cache,
+            properties,
+            prefix_filter,
         })
     }
 
@@ -0,0 +1,164 @@
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use super::BlockMeta;
+use crate::block::BlockBuilder;
+
+/// Locates a data block inside an index partition. Index partitions are encoded as regular blocks,
+/// mapping the first key of each data block to its handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHandle {
+    /// Index of the data block in the table.
+    pub block_idx: usize,
+    /// Offset of the data block.
+    pub offset: usize,
+    /// Length of the data block.
+    pub len: usize,
+}
+
+impl BlockHandle {
+    const ENCODED_SIZE: usize = std::mem::size_of::<u32>() * 3;
+
+    pub fn encode(&self) -> [u8; Self::ENCODED_SIZE] {
+        let mut buf = [0; Self::ENCODED_SIZE];
+        let mut slice = &mut buf[..];
+        slice.put_u32(self.block_idx as u32);
+        slice.put_u32(self.offset as u32);
+        slice.put_u32(self.len as u32);
+        buf
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Result<Self> {
+        if buf.len() != Self::ENCODED_SIZE {
+            bail!("invalid block handle of {} bytes", buf.len());
+        }
+        Ok(Self {
+            block_idx: buf.get_u32() as usize,
+            offset: buf.get_u32() as usize,
+            len: buf.get_u32() as usize,
+        })
+    }
+}
+
+/// Locates an index partition. The top-level index of a partitioned index is a list of these.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexPartition {
+    /// Offset of the index partition.
+    pub offset: usize,
+    /// Length of the index partition.
+    pub len: usize,
+    /// Index of the first data block covered by the partition.
+    pub first_block_idx: usize,
+    /// The first key of the first data block covered by the partition.
+    pub first_key: Bytes,
+}
+
+impl IndexPartition {
+    /// Build the index partitions for the data blocks described by `block_metas`, where the data
+    /// section ends at `data_end`. The partitions are appended to `buf`.
+    pub fn build_partitions(
+        block_metas: &[BlockMeta],
+        data_end: usize,
+        partition_size: usize,
+        buf: &mut Vec<u8>,
+    ) -> Vec<IndexPartition> {
+        let mut partitions = Vec::new();
+        let mut builder = BlockBuilder::new(partition_size);
+        let mut first_block_idx = 0;
+        for (block_idx, meta) in block_metas.iter().enumerate() {
+            let end = block_metas
+                .get(block_idx + 1)
+                .map_or(data_end, |next| next.offset);
+            let handle = BlockHandle {
+                block_idx,
+                offset: meta.offset,
+                len: end - meta.offset,
+            };
+            if builder.add(&meta.first_key, &handle.encode()) {
+                continue;
+            }
+            let full = std::mem::replace(&mut builder, BlockBuilder::new(partition_size));
+            Self::finish_partition(full, block_metas, first_block_idx, buf, &mut partitions);
+            first_block_idx = block_idx;
+            assert!(builder.add(&meta.first_key, &handle.encode()));
+        }
+        Self::finish_partition(builder, block_metas, first_block_idx, buf, &mut partitions);
+        partitions
+    }
+
+    fn finish_partition(
+        builder: BlockBuilder,
+        block_metas: &[BlockMeta],
+        first_block_idx: usize,
+        buf: &mut Vec<u8>,
+        partitions: &mut Vec<IndexPartition>,
+    ) {
+        let encoded = builder.build().encode();
+        partitions.push(IndexPartition {
+            offset: buf.len(),
+            len: encoded.len(),
+            first_block_idx,
+            first_key: block_metas[first_block_idx].first_key.clone(),
+        });
+        buf.extend(encoded);
+    }
+
+    /// Encode the top-level index to a buffer.
+    pub fn encode_top_level_index(
+        partitions: &[IndexPartition],
+        num_of_blocks: usize,
+        buf: &mut Vec<u8>,
+    ) {
+        buf.put_u32(num_of_blocks as u32);
+        for partition in partitions {
+            buf.put_u32(partition.offset as u32);
+            buf.put_u32(partition.len as u32);
+            buf.put_u32(partition.first_block_idx as u32);
+            buf.put_u16(partition.first_key.len() as u16);
+            buf.put_slice(&partition.first_key);
+        }
+    }
+
+    /// Decode the top-level index from a buffer, returns the partitions and the number of data
+    /// blocks.
+    pub fn decode_top_level_index(mut buf: impl Buf) -> Result<(Vec<IndexPartition>, usize)> {
+        const HEADER_SIZE: usize = std::mem::size_of::<u32>() * 3 + std::mem::size_of::<u16>();
+        if buf.remaining() < std::mem::size_of::<u32>() {
+            bail!("top-level index is truncated");
+        }
+        let num_of_blocks = buf.get_u32() as usize;
+        let mut partitions = Vec::new();
+        while buf.has_remaining() {
+            if buf.remaining() < HEADER_SIZE {
+                bail!("top-level index is truncated");
+            }
+            let offset = buf.get_u32() as usize;
+            let len = buf.get_u32() as usize;
+            let first_block_idx = buf.get_u32() as usize;
+            let first_key_len = buf.get_u16() as usize;
+            if buf.remaining() < first_key_len {
+                bail!("top-level index is truncated");
+            }
+            let first_key = buf.copy_to_bytes(first_key_len);
+            partitions.push(IndexPartition {
+                offset,
+                len,
+                first_block_idx,
+                first_key,
+            });
+        }
+        Ok((partitions, num_of_blocks))
+    }
+}
+
+/// The index of the data blocks in an SST.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockIndex {
+    /// All block metas, kept in memory.
+    Flat(Vec<BlockMeta>),
+    /// A top-level index in memory. Index partitions are read on demand.
+    Partitioned {
+        partitions: Vec<IndexPartition>,
+        num_of_blocks: usize,
+    },
+}
@@ -1,16 +1,52 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 use anyhow::Result;
 
 use super::SsTable;
-use crate::block::BlockIterator;
+use crate::block::{Block, BlockIterator};
 use crate::iterators::StorageIterator;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+
+/// Number of blocks `next` must read in a row before the iterator starts reading ahead.
+const READAHEAD_TRIGGER: usize = 2;
+
+/// The readahead state of an iterator. Once the iterator moves through a few blocks in a row, it
+/// fetches the next blocks with one larger read instead of one read per block. The size of the
+/// read starts at two blocks and doubles up to `max_blocks` while the scan stays sequential. Blocks
+/// read ahead are kept by the iterator and not inserted into the block cache, so that long scans
+/// do not evict the hot blocks.
+#[derive(Default)]
+struct Readahead {
+    /// Maximum number of blocks fetched in one read, readahead is disabled if less than 2.
+    max_blocks: usize,
+    /// Number of blocks read in a row by `next`.
+    sequential_blocks: usize,
+    /// Number of blocks fetched by the last readahead.
+    window: usize,
+    /// Blocks fetched ahead, the first one being `first_block_idx`.
+    blocks: VecDeque<Arc<Block>>,
+    first_block_idx: usize,
+}
+
+impl Readahead {
+    fn reset(&mut self) {
+        self.sequential_blocks = 0;
+        self.window = 0;
+        self.blocks.clear();
+    }
+}
 
 /// An iterator over the contents of an SSTable.
 pub struct SsTableIterator {
+    /// Pins the table, so that its file stays open while the iterator is in use even if the table
+    /// is evicted from the table cache.
     table: Arc<SsTable>,
     blk_iter: BlockIterator,
     blk_idx: usize,
+    readahead: Readahead,
+    /// Limits the rate of the block reads of `next`, if set.
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
 }
 
 impl SsTableIterator {
@@ -28,20 +64,40 @@ impl SsTableIterator {
             blk_iter,
             table,
             blk_idx,
+            readahead: Readahead::default(),
+            rate_limiter: None,
         };
         Ok(iter)
     }
 
+    /// Create a new iterator and seek to the first key-value pair, letting a rate limiter grant the
+    /// blocks it reads from the file in the lane of `priority`, the first one included. Blocks
+    /// served from memory are not counted.
+    pub fn create_and_seek_to_first_with_rate_limiter(
+        table: Arc<SsTable>,
+        rate_limiter: Arc<RateLimiter>,
+        priority: IoPriority,
+    ) -> Result<Self> {
+        if !table.is_in_memory() && !table.is_block_cached(0) {
+            let (_, len) = table.block_range(0)?;
+            rate_limiter.request(len, priority);
+        }
+        let mut iter = Self::create_and_seek_to_first(table)?;
+        iter.rate_limiter = Some((rate_limiter, priority));
+        Ok(iter)
+    }
+
     /// Seek to the first key-value pair.
     pub fn seek_to_first(&mut self) -> Result<()> {
         let (blk_idx, blk_iter) = Self::seek_to_first_inner(&self.table)?;
+        self.readahead.reset();
         self.blk_idx = blk_idx;
         self.blk_iter = blk_iter;
         Ok(())
     }
 
     fn seek_to_key_inner(table: &Arc<SsTable>, key: &[u8]) -> Result<(usize, BlockIterator)> {
-        let mut blk_idx = table.find_block_idx(key);
+        let mut blk_idx = table.find_block_idx(key)?;
         let mut blk_iter =
             BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
         if !blk_iter.is_valid() {
@@ -61,6 +117,8 @@ impl SsTableIterator {
             blk_iter,
             table,
             blk_idx,
+            readahead: Readahead::default(),
+            rate_limiter: None,
         };
         Ok(iter)
     }
@@ -68,10 +126,61 @@ impl SsTableIterator {
     /// Seek to the first key-value pair which >= `key`.
     pub fn seek_to_key(&mut self, key: &[u8]) -> Result<()> {
         let (blk_idx, blk_iter) = Self::seek_to_key_inner(&self.table, key)?;
+        self.readahead.reset();
         self.blk_iter = blk_iter;
         self.blk_idx = blk_idx;
         Ok(())
     }
+
+    /// Read up to `max_blocks` blocks at a time once the iterator is moving through the table
+    /// sequentially. Zero or one disables readahead.
+    pub fn set_readahead(&mut self, max_blocks: usize) {
+        self.readahead.max_blocks = max_blocks;
+    }
+
+    /// Wait for the rate limiter, if any, to grant reading blocks `[first_block_idx,
+    /// first_block_idx + count)` from the file.
+    fn request_blocks(&self, first_block_idx: usize, count: usize) -> Result<()> {
+        let Some((ref rate_limiter, priority)) = self.rate_limiter else {
+            return Ok(());
+        };
+        let (start, _) = self.table.block_range(first_block_idx)?;
+        let (offset, len) = self.table.block_range(first_block_idx + count - 1)?;
+        rate_limiter.request(offset + len - start, priority);
+        Ok(())
+    }
+
+    /// Read the block the iterator moved to in `next`, from the blocks read ahead if possible.
+    fn read_next_block(&mut self) -> Result<Arc<Block>> {
+        let readahead = &mut self.readahead;
+        readahead.sequential_blocks += 1;
+        if readahead.first_block_idx == self.blk_idx {
+            if let Some(block) = readahead.blocks.pop_front() {
+                readahead.first_block_idx += 1;
+                return Ok(block);
+            }
+        }
+        // Files in memory are read without I/O, there is nothing to gain from reading ahead.
+        if readahead.max_blocks < 2
+            || readahead.sequential_blocks < READAHEAD_TRIGGER
+            || self.table.is_in_memory()
+            || self.table.is_block_cached(self.blk_idx)
+        {
+            if !self.table.is_in_memory() && !self.table.is_block_cached(self.blk_idx) {
+                self.request_blocks(self.blk_idx, 1)?;
+            }
+            return self.table.read_block_cached(self.blk_idx);
+        }
+        readahead.window = (readahead.window * 2).clamp(2, readahead.max_blocks);
+        let count = readahead
+            .window
+            .min(self.table.num_of_blocks() - self.blk_idx);
+        self.request_blocks(self.blk_idx, count)?;
+        let readahead = &mut self.readahead;
+        readahead.blocks = self.table.read_blocks(self.blk_idx, count)?.into();
+        readahead.first_block_idx = self.blk_idx + 1;
+        Ok(readahead.blocks.pop_front().unwrap())
+    }
 }
 
 impl StorageIterator for SsTableIterator {
@@ -92,9 +201,7 @@ impl StorageIterator for SsTableIterator {
         if !self.blk_iter.is_valid() {
             self.blk_idx += 1;
             if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
-                    self.table.read_block_cached(self.blk_idx)?,
-                );
+                self.blk_iter = BlockIterator::create_and_seek_to_first(self.read_next_block()?);
             }
         }
         Ok(())
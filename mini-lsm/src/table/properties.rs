@@ -0,0 +1,105 @@
+use std::ops::Bound;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+/// Table-level statistics, written by [`super::SsTableBuilder`] after the block metas.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableProperties {
+    /// Number of key-value pairs, including tombstones.
+    pub num_entries: u64,
+    /// Number of tombstones (entries with an empty value).
+    pub num_tombstones: u64,
+    /// Total size of all keys in bytes.
+    pub raw_key_size: u64,
+    /// Total size of all user values in bytes, as written by the user rather than as stored in
+    /// the table.
+    pub raw_value_size: u64,
+    /// The smallest key in the table.
+    pub first_key: Bytes,
+    /// The largest key in the table.
+    pub last_key: Bytes,
+    /// Seconds since the Unix epoch when the table was built.
+    pub creation_time: u64,
+    /// Number of index partitions, zero if the table has a flat index.
+    pub num_index_partitions: u64,
+}
+
+impl TableProperties {
+    /// Record a key-value pair added to the table, whose user value is `value_size` bytes long.
+    pub(crate) fn add(&mut self, key: &[u8], value_size: usize) {
+        self.num_entries += 1;
+        if value_size == 0 {
+            self.num_tombstones += 1;
+        }
+        self.raw_key_size += key.len() as u64;
+        self.raw_value_size += value_size as u64;
+    }
+
+    /// Check if `key` falls in the key range of the table.
+    pub(crate) fn may_contain_key(&self, key: &[u8]) -> bool {
+        self.first_key <= key && key <= self.last_key
+    }
+
+    /// Check if the key range of the table intersects with the given range.
+    pub(crate) fn range_overlap(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+        let above_lower = match lower {
+            Bound::Included(key) => self.last_key >= key,
+            Bound::Excluded(key) => self.last_key > key,
+            Bound::Unbounded => true,
+        };
+        let below_upper = match upper {
+            Bound::Included(key) => self.first_key <= key,
+            Bound::Excluded(key) => self.first_key < key,
+            Bound::Unbounded => true,
+        };
+        above_lower && below_upper
+    }
+
+    /// Encode the properties to a buffer.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u64(self.num_entries);
+        buf.put_u64(self.num_tombstones);
+        buf.put_u64(self.raw_key_size);
+        buf.put_u64(self.raw_value_size);
+        buf.put_u64(self.creation_time);
+        buf.put_u64(self.num_index_partitions);
+        buf.put_u16(self.first_key.len() as u16);
+        buf.put_slice(&self.first_key);
+        buf.put_u16(self.last_key.len() as u16);
+        buf.put_slice(&self.last_key);
+    }
+
+    /// Decode the properties from a buffer.
+    pub fn decode(mut buf: impl Buf) -> Result<Self> {
+        if buf.remaining() < std::mem::size_of::<u64>() * 6 + std::mem::size_of::<u16>() {
+            bail!("table properties too short");
+        }
+        let num_entries = buf.get_u64();
+        let num_tombstones = buf.get_u64();
+        let raw_key_size = buf.get_u64();
+        let raw_value_size = buf.get_u64();
+        let creation_time = buf.get_u64();
+        let num_index_partitions = buf.get_u64();
+        let first_key_len = buf.get_u16() as usize;
+        if buf.remaining() < first_key_len + std::mem::size_of::<u16>() {
+            bail!("table properties too short");
+        }
+        let first_key = buf.copy_to_bytes(first_key_len);
+        let last_key_len = buf.get_u16() as usize;
+        if buf.remaining() < last_key_len {
+            bail!("table properties too short");
+        }
+        let last_key = buf.copy_to_bytes(last_key_len);
+        Ok(Self {
+            num_entries,
+            num_tombstones,
+            raw_key_size,
+            raw_value_size,
+            first_key,
+            last_key,
+            creation_time,
+            num_index_partitions,
+        })
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use super::{FileObject, PrefixFilter, SsTable, SsTableBuilder, SsTableIterator, TableProperties};
+use crate::blob::StoredValue;
+use crate::env::{Env, PosixEnv};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::LsmStorageOptions;
+
+/// Writes an SST file outside of a running storage, e.g. to be added to a storage with
+/// [`crate::lsm_storage::LsmStorage::ingest_files`]. Keys must be added in strictly increasing
+/// order. The file follows the table options of the [`LsmStorageOptions`] it is created with.
+pub struct SstFileWriter {
+    builder: SsTableBuilder,
+    env: Arc<dyn Env>,
+    path: PathBuf,
+    last_key: Option<Vec<u8>>,
+}
+
+impl SstFileWriter {
+    /// Create a writer for a file at `path` in the environment of `options`. Nothing is written
+    /// until [`SstFileWriter::finish`] is called.
+    pub fn create(options: &LsmStorageOptions, path: impl AsRef<Path>) -> Self {
+        Self {
+            builder: options.new_sst_builder(options.block_size),
+            env: options.env.clone(),
+            path: path.as_ref().to_path_buf(),
+            last_key: None,
+        }
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8], value_size: usize) -> Result<()> {
+        if key.is_empty() {
+            bail!("key cannot be empty");
+        }
+        if self.last_key.as_deref().is_some_and(|last| last >= key) {
+            bail!(
+                "key {:?} is not greater than the previous key",
+                Bytes::copy_from_slice(key)
+            );
+        }
+        self.builder.add_with_value_size(key, value, value_size);
+        self.last_key = Some(key.to_vec());
+        Ok(())
+    }
+
+    /// Add a key-value pair.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if value.is_empty() {
+            bail!("value cannot be empty");
+        }
+        let mut buf = Vec::with_capacity(value.len() + 1);
+        StoredValue::Inline(value).encode(&mut buf);
+        self.add(key, &buf, value.len())
+    }
+
+    /// Add a tombstone, which deletes the key from the storage the file is ingested into.
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.add(key, b"", 0)
+    }
+
+    /// Write the file and sync it. Returns the properties of the table. A file must hold at least
+    /// one entry.
+    pub fn finish(self) -> Result<TableProperties> {
+        if self.last_key.is_none() {
+            bail!("cannot write an empty SST");
+        }
+        let table = self
+            .builder
+            .build_with_env(0, None, self.env.as_ref(), &self.path)?;
+        Ok(table.properties().clone())
+    }
+}
+
+/// Reads an SST file outside of a running storage. Values are returned as they were written by
+/// [`SstFileWriter::put`], tombstones as empty values. Values separated into blob files cannot be
+/// read without the storage, and are reported as errors.
+pub struct SstFileReader {
+    table: Arc<SsTable>,
+}
+
+/// Get the value of an entry as written by the user, or an empty value for a tombstone.
+fn user_value(raw: &[u8]) -> Result<&[u8]> {
+    if raw.is_empty() {
+        return Ok(raw);
+    }
+    match StoredValue::decode(raw)? {
+        StoredValue::Inline(value) => Ok(value),
+        StoredValue::Blob(pointer) => {
+            bail!("value is stored in blob file {}", pointer.file_id)
+        }
+    }
+}
+
+impl SstFileReader {
+    /// Open a file of the operating system's file system.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_env(&PosixEnv, path)
+    }
+
+    /// Open a file of an environment.
+    pub fn open_with_env(env: &dyn Env, path: impl AsRef<Path>) -> Result<Self> {
+        let table = SsTable::open(0, None, FileObject::open(env, path.as_ref())?)?;
+        Ok(Self {
+            table: Arc::new(table),
+        })
+    }
+
+    /// Get the properties of the table.
+    pub fn properties(&self) -> &TableProperties {
+        self.table.properties()
+    }
+
+    /// Get the prefix bloom filter of the table, if it was written with a prefix extractor.
+    pub fn prefix_filter(&self) -> Option<&PrefixFilter> {
+        self.table.prefix_filter()
+    }
+
+    /// Get the value of a key, or `None` if the file does not hold the key.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        match self.table.get(key)? {
+            Some(raw) => Ok(Some(raw.slice_ref(user_value(&raw)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Create an iterator over all entries.
+    pub fn iter(&self) -> Result<SstFileIterator> {
+        SstFileIterator::new(SsTableIterator::create_and_seek_to_first(
+            self.table.clone(),
+        )?)
+    }
+
+    /// Create an iterator over the entries starting from the first key that is `>= key`.
+    pub fn iter_from(&self, key: &[u8]) -> Result<SstFileIterator> {
+        SstFileIterator::new(SsTableIterator::create_and_seek_to_key(
+            self.table.clone(),
+            key,
+        )?)
+    }
+
+    /// Read the whole file and check that it is well-formed: the keys are non-empty and strictly
+    /// increasing, the entries match the properties, and all values can be read.
+    pub fn verify(&self) -> Result<()> {
+        let properties = self.properties();
+        let mut iter = self.iter()?;
+        let mut prev_key: Option<Vec<u8>> = None;
+        let mut num_entries = 0;
+        while iter.is_valid() {
+            let key = iter.key();
+            if key.is_empty() {
+                bail!("empty key");
+            }
+            match prev_key {
+                None if key != properties.first_key => {
+                    bail!("first key does not match properties")
+                }
+                Some(ref prev) if prev.as_slice() >= key => bail!("keys are not sorted"),
+                _ => {}
+            }
+            prev_key = Some(key.to_vec());
+            num_entries += 1;
+            iter.next()?;
+        }
+        if num_entries == 0 {
+            bail!("SST is empty");
+        }
+        if num_entries != properties.num_entries
+            || prev_key.as_deref() != Some(&properties.last_key[..])
+        {
+            bail!("entries do not match properties");
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the entries of an [`SstFileReader`], yielding the values as written by the
+/// user. Moving onto a value that cannot be read fails.
+pub struct SstFileIterator {
+    iter: SsTableIterator,
+}
+
+impl SstFileIterator {
+    fn new(iter: SsTableIterator) -> Result<Self> {
+        let iter = Self { iter };
+        iter.check_value()?;
+        Ok(iter)
+    }
+
+    fn check_value(&self) -> Result<()> {
+        if self.iter.is_valid() {
+            user_value(self.iter.value())?;
+        }
+        Ok(())
+    }
+}
+
+impl StorageIterator for SstFileIterator {
+    fn value(&self) -> &[u8] {
+        // Values are checked when the iterator moves onto them.
+        user_value(self.iter.value()).unwrap()
+    }
+
+    fn key(&self) -> &[u8] {
+        self.iter.key()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.iter.next()?;
+        self.check_value()
+    }
+}
@@ -1,10 +1,16 @@
+use std::path::Path;
 use std::sync::Arc;
 
 use bytes::Bytes;
 use tempfile::{tempdir, TempDir};
 
 use super::*;
+use crate::blob::StoredValue;
+use crate::block_cache::BlockCache;
+use crate::env::{FaultInjectionEnv, PosixEnv};
 use crate::iterators::StorageIterator;
+use crate::prefix_extractor::FixedPrefixExtractor;
+use crate::rate_limiter::{IoPriority, RateLimiter};
 use crate::table::SsTableBuilder;
 
 #[test]
@@ -15,6 +21,13 @@ fn test_sst_build_single_key() {
     builder.build_for_test(dir.path().join("1.sst")).unwrap();
 }
 
+#[test]
+fn test_sst_build_empty() {
+    let dir = tempdir().unwrap();
+    let builder = SsTableBuilder::new(16);
+    assert!(builder.build_for_test(dir.path().join("1.sst")).is_err());
+}
+
 #[test]
 fn test_sst_build_two_blocks() {
     let mut builder = SsTableBuilder::new(16);
@@ -61,9 +74,9 @@ fn test_sst_build_all() {
 #[test]
 fn test_sst_decode() {
     let (_dir, sst) = generate_sst();
-    let meta = sst.block_metas.clone();
+    let meta = sst.index.clone();
     let new_sst = SsTable::open_for_test(sst.file).unwrap();
-    assert_eq!(new_sst.block_metas, meta);
+    assert_eq!(new_sst.index, meta);
 }
 
 fn as_bytes(x: &[u8]) -> Bytes {
@@ -128,3 +141,424 @@ fn test_sst_seek_key() {
         iter.seek_to_key(b"k").unwrap();
     }
 }
+
+#[test]
+fn test_sst_properties() {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        let value = if idx % 10 == 0 { vec![] } else { value_of(idx) };
+        builder.add(&key_of(idx), &value);
+    }
+    let dir = tempdir().unwrap();
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let properties = sst.properties().clone();
+    assert_eq!(properties.num_entries, num_of_keys() as u64);
+    assert_eq!(properties.num_tombstones, 10);
+    assert_eq!(properties.raw_key_size, 7 * num_of_keys() as u64);
+    assert_eq!(properties.raw_value_size, 16 * 90);
+    assert_eq!(properties.first_key, key_of(0));
+    assert_eq!(properties.last_key, key_of(num_of_keys() - 1));
+    assert!(properties.creation_time > 0);
+
+    let new_sst = SsTable::open_for_test(sst.file).unwrap();
+    assert_eq!(new_sst.properties(), &properties);
+}
+
+fn generate_sst_with_partitioned_index(block_cache: Option<Arc<BlockCache>>) -> (TempDir, SsTable) {
+    let mut builder = SsTableBuilder::new(128).with_partitioned_index(64);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let sst = builder.build(1, block_cache, path).unwrap();
+    (dir, sst)
+}
+
+#[test]
+fn test_sst_partitioned_index() {
+    let (_dir, flat_sst) = generate_sst();
+    let (_dir, sst) = generate_sst_with_partitioned_index(None);
+    assert!(sst.properties().num_index_partitions > 1);
+    assert_eq!(sst.num_of_blocks(), flat_sst.num_of_blocks());
+    for idx in 0..sst.num_of_blocks() {
+        assert_eq!(
+            sst.read_block(idx).unwrap().encode(),
+            flat_sst.read_block(idx).unwrap().encode()
+        );
+    }
+    for i in 0..num_of_keys() * 5 + 5 {
+        let key = format!("key_{:03}", i).into_bytes();
+        assert_eq!(
+            sst.find_block_idx(&key).unwrap(),
+            flat_sst.find_block_idx(&key).unwrap(),
+        );
+    }
+    assert_eq!(sst.find_block_idx(b"k").unwrap(), 0);
+
+    let index = sst.index.clone();
+    let new_sst = SsTable::open_for_test(sst.file).unwrap();
+    assert_eq!(new_sst.index, index);
+}
+
+#[test]
+fn test_sst_partitioned_index_iterator() {
+    let block_cache = Arc::new(BlockCache::new(1 << 20));
+    let (_dir, sst) = generate_sst_with_partitioned_index(Some(block_cache.clone()));
+    let sst = Arc::new(sst);
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+    for i in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        assert_eq!(iter.value(), value_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+    for i in 0..num_of_keys() {
+        let iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            format!("key_{:03}", i * 5 - i.min(1)).as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(iter.key(), key_of(i));
+    }
+    // Index partitions are cached after the data blocks.
+    assert!(block_cache.contains_key(&(1, sst.num_of_blocks())));
+}
+
+#[test]
+fn test_sst_get_with_block_hash_index() {
+    let mut builder = SsTableBuilder::new(128).with_block_hash_index();
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    assert!(sst.read_block(0).unwrap().has_hash_index());
+    for i in 0..num_of_keys() {
+        assert_eq!(sst.get(&key_of(i)).unwrap().unwrap(), value_of(i));
+        assert!(sst
+            .get(&format!("key_{:03}", i * 5 + 1).into_bytes())
+            .unwrap()
+            .is_none());
+    }
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap();
+    for i in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_multi_get() {
+    let block_cache = Arc::new(BlockCache::new(1 << 20));
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let sst = builder
+        .build(1, Some(block_cache.clone()), dir.path().join("1.sst"))
+        .unwrap();
+
+    // Every key of the table, each followed by a missing key, and keys out of the table range.
+    let mut keys = vec![b"a".to_vec()];
+    for idx in 0..num_of_keys() {
+        keys.push(key_of(idx));
+        keys.push(format!("key_{:03}", idx * 5 + 1).into_bytes());
+    }
+    keys.push(b"z".to_vec());
+    let keys: Vec<_> = keys.iter().map(|key| &key[..]).collect();
+    let values = sst.multi_get(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(value, &sst.get(key).unwrap());
+    }
+    assert_eq!(values[1].as_deref(), Some(&value_of(0)[..]));
+    assert_eq!(values[2], None);
+
+    // Each block is read once for the batch.
+    let before = block_cache.stats();
+    sst.multi_get(&keys).unwrap();
+    let after = block_cache.stats();
+    assert_eq!(
+        (after.hits + after.misses - before.hits - before.misses) as usize,
+        sst.num_of_blocks()
+    );
+}
+
+#[test]
+fn test_sst_mmap() {
+    let (dir, sst) = generate_sst();
+    let path = dir.path().join("1.sst");
+    let file = FileObject::open_mmap(&PosixEnv, &path, u64::MAX).unwrap();
+    assert!(file.is_in_memory());
+    // Reads are slices of the same mapping.
+    let whole = file.read(0, file.size()).unwrap();
+    let part = file.read(10, 20).unwrap();
+    assert_eq!(part.as_ptr(), whole[10..].as_ptr());
+    assert!(file.read(file.size() - 4, 8).is_err());
+
+    let mmap_sst = SsTable::open(0, None, file).unwrap();
+    assert_eq!(mmap_sst.index, sst.index);
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(mmap_sst)).unwrap();
+    for i in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        assert_eq!(iter.value(), value_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_mmap_falls_back_to_pread() {
+    let (dir, sst) = generate_sst();
+    let path = dir.path().join("1.sst");
+    let file = FileObject::open_mmap(&PosixEnv, &path, sst.file.size() - 1).unwrap();
+    assert!(!file.is_in_memory());
+    assert_eq!(
+        file.read(0, file.size()).unwrap(),
+        sst.file.read(0, sst.file.size()).unwrap()
+    );
+}
+
+#[test]
+fn test_sst_read_blocks() {
+    let (_dir, sst) = generate_sst_with_partitioned_index(None);
+    let num_of_blocks = sst.num_of_blocks();
+    let blocks = sst.read_blocks(1, num_of_blocks - 1).unwrap();
+    assert_eq!(blocks.len(), num_of_blocks - 1);
+    for (idx, block) in blocks.iter().enumerate() {
+        assert_eq!(block.encode(), sst.read_block(idx + 1).unwrap().encode());
+    }
+}
+
+#[test]
+fn test_sst_iterator_readahead() {
+    let block_cache = Arc::new(BlockCache::new(1 << 20));
+    let (_dir, sst) = generate_sst_with_partitioned_index(Some(block_cache.clone()));
+    let sst = Arc::new(sst);
+    let num_of_blocks = sst.num_of_blocks();
+    assert!(num_of_blocks > 8);
+    for _ in 0..2 {
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        iter.set_readahead(4);
+        for i in 0..num_of_keys() {
+            assert_eq!(iter.key(), key_of(i));
+            assert_eq!(iter.value(), value_of(i));
+            iter.next().unwrap();
+        }
+        assert!(!iter.is_valid());
+    }
+    // Only the blocks read before the scan turned sequential went through the block cache.
+    let cached = (0..num_of_blocks)
+        .filter(|idx| sst.is_block_cached(*idx))
+        .count();
+    assert_eq!(cached, 2);
+
+    // A seek starts over with single block reads.
+    let mut iter = SsTableIterator::create_and_seek_to_key(sst.clone(), &key_of(50)).unwrap();
+    iter.set_readahead(4);
+    for i in 50..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_bloom_filter() {
+    let hashes: Vec<_> = (0..1000).map(|idx| Bloom::hash(&key_of(idx))).collect();
+    let bloom = Bloom::build_from_hashes(&hashes, 10);
+    let mut buf = Vec::new();
+    bloom.encode(&mut buf);
+    let bloom = Bloom::decode(&buf).unwrap();
+    for hash in &hashes {
+        assert!(bloom.may_contain(*hash));
+    }
+    let false_positives = (0..1000)
+        .filter(|idx| bloom.may_contain(Bloom::hash(format!("other_{}", idx).as_bytes())))
+        .count();
+    // About 1% with 10 bits per key.
+    assert!(false_positives < 50, "{} false positives", false_positives);
+}
+
+#[test]
+fn test_sst_prefix_filter() {
+    let extractor = Arc::new(FixedPrefixExtractor::new(6));
+    let mut builder = SsTableBuilder::new(128).with_prefix_bloom(extractor.clone(), 10);
+    // Keys `key_000` to `key_495`, with the prefixes `key_00` to `key_49`.
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let dir = tempdir().unwrap();
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let filter = sst.prefix_filter().unwrap().clone();
+    let sst = SsTable::open_for_test(sst.file).unwrap();
+    assert_eq!(sst.prefix_filter(), Some(&filter));
+
+    for idx in 0..50 {
+        let prefix = format!("key_{:02}", idx);
+        assert!(filter.may_contain(extractor.as_ref(), prefix.as_bytes()));
+        // Longer prefixes are checked with their extracted prefix.
+        assert!(filter.may_contain(extractor.as_ref(), format!("{}9", prefix).as_bytes()));
+    }
+    let false_positives = (50..100)
+        .filter(|idx| filter.may_contain(extractor.as_ref(), format!("key_{}", idx).as_bytes()))
+        .count();
+    assert!(false_positives < 5, "{} false positives", false_positives);
+    // Prefixes out of the domain, and filters of other extractors, cannot rule out any key.
+    assert!(filter.may_contain(extractor.as_ref(), b"key_"));
+    assert!(filter.may_contain(&FixedPrefixExtractor::new(5), b"key_5"));
+
+    // Without an extractor, there is no filter.
+    let (_dir, sst) = generate_sst();
+    assert!(sst.prefix_filter().is_none());
+}
+
+#[test]
+fn test_sst_approximate_offset() {
+    let (_dir, sst) = generate_sst();
+    let data_size = sst.data_size().unwrap();
+    assert_eq!(data_size, sst.block_meta_offset as u64);
+    assert_eq!(sst.approximate_offset_of(b"a").unwrap(), 0);
+    assert_eq!(sst.approximate_offset_of(&key_of(0)).unwrap(), 0);
+    assert_eq!(sst.approximate_offset_of(b"z").unwrap(), data_size);
+    let mut last_offset = 0;
+    for idx in 0..num_of_keys() {
+        let key = key_of(idx);
+        let offset = sst.approximate_offset_of(&key).unwrap();
+        let (block_offset, _) = sst.block_range(sst.find_block_idx(&key).unwrap()).unwrap();
+        assert_eq!(offset, block_offset as u64);
+        assert!(offset >= last_offset);
+        last_offset = offset;
+    }
+    assert!(last_offset > 0 && last_offset < data_size);
+
+    let (_dir, partitioned_sst) = generate_sst_with_partitioned_index(None);
+    assert_eq!(partitioned_sst.data_size().unwrap(), data_size);
+    for idx in 0..num_of_keys() {
+        assert_eq!(
+            partitioned_sst.approximate_offset_of(&key_of(idx)).unwrap(),
+            sst.approximate_offset_of(&key_of(idx)).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_sst_file_writer_reader() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let options = crate::lsm_storage::LsmStorageOptions {
+        block_size: 128,
+        index_partition_size: Some(64),
+        prefix_extractor: Some(Arc::new(FixedPrefixExtractor::new(4))),
+        ..Default::default()
+    };
+    let mut writer = SstFileWriter::create(&options, &path);
+    for idx in 0..num_of_keys() {
+        if idx % 10 == 0 {
+            writer.delete(&key_of(idx)).unwrap();
+        } else {
+            writer.put(&key_of(idx), &value_of(idx)).unwrap();
+        }
+    }
+    // Keys must be strictly increasing, non-empty, and values non-empty.
+    assert!(writer.put(&key_of(num_of_keys() - 1), b"value").is_err());
+    assert!(writer.put(&key_of(0), b"value").is_err());
+    assert!(writer.put(b"zzz", b"").is_err());
+    let properties = writer.finish().unwrap();
+    assert_eq!(properties.num_entries, num_of_keys() as u64);
+    assert_eq!(properties.num_tombstones, 10);
+    assert_eq!(properties.first_key, key_of(0));
+
+    let reader = SstFileReader::open(&path).unwrap();
+    assert_eq!(reader.properties(), &properties);
+    assert!(reader.properties().num_index_partitions > 1);
+    assert!(reader.prefix_filter().is_some());
+    reader.verify().unwrap();
+    for idx in 0..num_of_keys() {
+        let expected = if idx % 10 == 0 {
+            Bytes::new()
+        } else {
+            Bytes::from(value_of(idx))
+        };
+        assert_eq!(reader.get(&key_of(idx)).unwrap(), Some(expected));
+    }
+    assert_eq!(reader.get(b"key_").unwrap(), None);
+
+    let mut iter = reader.iter_from(&key_of(55)).unwrap();
+    for idx in 55..num_of_keys() {
+        assert_eq!(iter.key(), key_of(idx));
+        if idx % 10 == 0 {
+            assert!(iter.value().is_empty());
+        } else {
+            assert_eq!(iter.value(), value_of(idx));
+        }
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+
+    // Writers of empty files fail.
+    let writer = SstFileWriter::create(&options, dir.path().join("2.sst"));
+    assert!(writer.finish().is_err());
+    assert!(SstFileReader::open(dir.path().join("2.sst")).is_err());
+}
+
+#[test]
+fn test_sst_file_reader_rejects_blob_values() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut builder = SsTableBuilder::new(128);
+    let mut buf = Vec::new();
+    StoredValue::Inline(b"inline").encode(&mut buf);
+    builder.add(b"a", &buf);
+    buf.clear();
+    StoredValue::Blob(crate::blob::BlobPointer {
+        file_id: 7,
+        offset: 0,
+        len: 100,
+    })
+    .encode(&mut buf);
+    builder.add(b"b", &buf);
+    builder.build(0, None, &path).unwrap();
+
+    let reader = SstFileReader::open(&path).unwrap();
+    assert_eq!(reader.get(b"a").unwrap(), Some(Bytes::from("inline")));
+    assert!(reader.get(b"b").is_err());
+    let mut iter = reader.iter().unwrap();
+    assert_eq!(iter.value(), b"inline");
+    assert!(iter.next().is_err());
+    assert!(reader.iter_from(b"b").is_err());
+    assert!(reader.verify().is_err());
+}
+
+#[test]
+fn test_file_object_create_with_rate_limiter() {
+    let env = FaultInjectionEnv::new();
+    let path = Path::new("/table.sst");
+    let data = vec![b'x'; 100_000];
+    // Count the I/O calls: creating, syncing and opening the file, plus one call per chunk.
+    let ops_to_create = |rate_limiter: &RateLimiter| {
+        (0..)
+            .find(|&ops| {
+                env.fail_after(ops);
+                let result = FileObject::create_with_rate_limiter(
+                    &env,
+                    path,
+                    data.clone(),
+                    rate_limiter,
+                    IoPriority::Flush,
+                );
+                env.clear_failures();
+                result.is_ok()
+            })
+            .unwrap()
+    };
+    // Without a limit, the file is written at once.
+    assert_eq!(ops_to_create(&RateLimiter::new(0)), 4);
+    // 40 KB per burst.
+    assert_eq!(ops_to_create(&RateLimiter::new(400_000)), 6);
+    let file = FileObject::open(&env, path).unwrap();
+    assert_eq!(file.read(0, data.len() as u64).unwrap(), data);
+}
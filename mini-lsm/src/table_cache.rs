@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::table::SsTable;
+
+#[derive(Default)]
+struct LruState {
+    /// Open SSTs by id, with the tick of their last use.
+    tables: HashMap<usize, (Arc<SsTable>, u64)>,
+    /// SST ids by the tick of their last use, from least to most recently used.
+    lru: BTreeMap<u64, usize>,
+    next_tick: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, sst_id: usize) -> Option<Arc<SsTable>> {
+        let tick = self.next_tick;
+        let (table, last_used) = self.tables.get_mut(&sst_id)?;
+        self.lru.remove(last_used);
+        *last_used = tick;
+        self.lru.insert(tick, sst_id);
+        self.next_tick += 1;
+        Some(table.clone())
+    }
+
+    fn remove(&mut self, sst_id: usize) -> Option<Arc<SsTable>> {
+        let (table, last_used) = self.tables.remove(&sst_id)?;
+        self.lru.remove(&last_used);
+        Some(table)
+    }
+}
+
+/// Keeps recently used SSTs open, keyed by SST id. An open SST holds a file descriptor and the
+/// parsed index, so the number of cached SSTs bounds the number of open files. Evicting an SST
+/// closes its file once no iterator holds the table anymore.
+///
+/// Unlike the block cache, this is a plain LRU list rather than a moka cache: moka may drop
+/// evicted values late from its housekeeper threads, and an SST holds the block cache, whose
+/// own moka cache then deadlocks waiting for those threads when it is dropped.
+pub struct TableCache {
+    state: Mutex<LruState>,
+    capacity: Option<usize>,
+}
+
+impl TableCache {
+    /// Create a table cache holding up to `max_open_files` SSTs, or all of them if `None`.
+    pub fn new(max_open_files: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(LruState::default()),
+            capacity: max_open_files,
+        }
+    }
+
+    /// Get an SST from the cache, or open it with `init` and insert it on a miss. The SST is
+    /// opened without holding the lock, so concurrent misses on the same SST may both open it,
+    /// and all but the first one to be inserted are dropped.
+    pub fn try_get_with(
+        &self,
+        sst_id: usize,
+        init: impl FnOnce() -> Result<Arc<SsTable>>,
+    ) -> Result<Arc<SsTable>> {
+        if let Some(table) = self.state.lock().touch(sst_id) {
+            return Ok(table);
+        }
+        let table = init()?;
+        let mut state = self.state.lock();
+        if let Some(table) = state.touch(sst_id) {
+            return Ok(table);
+        }
+        let evicted = self.insert_locked(&mut state, table.clone());
+        drop(state);
+        drop(evicted);
+        Ok(table)
+    }
+
+    /// Insert an SST that has just been built, so that it does not need to be opened again.
+    pub fn insert(&self, table: Arc<SsTable>) {
+        let mut state = self.state.lock();
+        let evicted = self.insert_locked(&mut state, table);
+        // Close the evicted files after releasing the lock.
+        drop(state);
+        drop(evicted);
+    }
+
+    /// Insert an SST, and return the SSTs evicted to stay within the capacity.
+    fn insert_locked(&self, state: &mut LruState, table: Arc<SsTable>) -> Vec<Arc<SsTable>> {
+        let sst_id = table.sst_id();
+        let mut evicted: Vec<_> = state.remove(sst_id).into_iter().collect();
+        let tick = state.next_tick;
+        state.next_tick += 1;
+        state.tables.insert(sst_id, (table, tick));
+        state.lru.insert(tick, sst_id);
+        if let Some(capacity) = self.capacity {
+            while state.tables.len() > capacity {
+                let (_, lru_id) = state.lru.pop_first().unwrap();
+                evicted.push(state.tables.remove(&lru_id).unwrap().0);
+            }
+        }
+        evicted
+    }
+
+    /// Check if an SST is open in the cache.
+    pub fn contains_key(&self, sst_id: usize) -> bool {
+        self.state.lock().tables.contains_key(&sst_id)
+    }
+
+    /// Close an SST, e.g. because it has been deleted.
+    pub fn invalidate(&self, sst_id: usize) {
+        let table = self.state.lock().remove(sst_id);
+        drop(table);
+    }
+
+    /// Get the number of open SSTs in the cache.
+    pub fn entry_count(&self) -> u64 {
+        self.state.lock().tables.len() as u64
+    }
+}
@@ -1 +1,19 @@
+pub mod approximate_tests;
+pub mod backup_tests;
+pub mod blob_tests;
+pub mod bulk_load_tests;
+pub mod checkpoint_tests;
+pub mod crash_tests;
 pub mod day4_tests;
+pub mod event_listener_tests;
+mod harness;
+pub mod ingest_tests;
+pub mod mem_env_tests;
+pub mod model_tests;
+pub mod multi_get_tests;
+pub mod prefix_scan_tests;
+pub mod range_pruning_tests;
+pub mod rate_limiter_tests;
+pub mod row_cache_tests;
+pub mod stats_tests;
+pub mod table_cache_tests;
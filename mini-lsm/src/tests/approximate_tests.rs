@@ -0,0 +1,140 @@
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{ApproximateOptions, LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{key_of, value_of};
+
+/// Write 10 SSTs of 200 keys each, with disjoint key ranges.
+fn open_with_sstables() -> (tempfile::TempDir, LsmStorage) {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            block_size: 512,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for sst in 0..10 {
+        for idx in sst * 200..(sst + 1) * 200 {
+            storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    (dir, storage)
+}
+
+#[test]
+fn test_approximate_whole_range() {
+    let (_dir, storage) = open_with_sstables();
+    let options = ApproximateOptions::default();
+    let total_size: u64 = storage
+        .inner
+        .read()
+        .l0_sstables
+        .iter()
+        .map(|table| table.file_size())
+        .sum();
+    assert_eq!(
+        storage
+            .approximate_size(Bound::Unbounded, Bound::Unbounded, &options)
+            .unwrap(),
+        total_size
+    );
+    assert_eq!(
+        storage
+            .approximate_count(Bound::Unbounded, Bound::Unbounded, &options)
+            .unwrap(),
+        2000
+    );
+    // A range covering whole SSTs.
+    assert_eq!(
+        storage
+            .approximate_count(
+                Bound::Included(&key_of(200)),
+                Bound::Excluded(&key_of(600)),
+                &options
+            )
+            .unwrap(),
+        400
+    );
+    // A range out of all SSTs.
+    assert_eq!(
+        storage
+            .approximate_size(Bound::Excluded(&key_of(1999)), Bound::Unbounded, &options)
+            .unwrap(),
+        0
+    );
+}
+
+#[test]
+fn test_approximate_partial_range() {
+    let (_dir, storage) = open_with_sstables();
+    let options = ApproximateOptions::default();
+    // Each block holds about 7 entries, so the estimate is off by up to a block per SST.
+    for (start, end) in [(0, 1000), (150, 250), (333, 1777), (50, 60)] {
+        let count = storage
+            .approximate_count(
+                Bound::Included(&key_of(start)),
+                Bound::Excluded(&key_of(end)),
+                &options,
+            )
+            .unwrap() as i64;
+        let expected = (end - start) as i64;
+        assert!(
+            (count - expected).abs() <= 20,
+            "{}..{}: {} entries estimated",
+            start,
+            end,
+            count
+        );
+        let size = storage
+            .approximate_size(
+                Bound::Included(&key_of(start)),
+                Bound::Excluded(&key_of(end)),
+                &options,
+            )
+            .unwrap();
+        let entry_size = (key_of(0).len() + value_of(0, 0).len()) as u64;
+        assert!(size >= (expected as u64).saturating_sub(20) * entry_size);
+    }
+}
+
+#[test]
+fn test_approximate_include_memtables() {
+    let (_dir, storage) = open_with_sstables();
+    for idx in 2000..2100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    let lower = Bound::Included(&key_of(2000)[..]);
+    assert_eq!(
+        storage
+            .approximate_count(lower, Bound::Unbounded, &ApproximateOptions::default())
+            .unwrap(),
+        0
+    );
+    let options = ApproximateOptions {
+        include_memtables: true,
+    };
+    assert_eq!(
+        storage
+            .approximate_count(lower, Bound::Unbounded, &options)
+            .unwrap(),
+        100
+    );
+    assert_eq!(
+        storage
+            .approximate_size(lower, Bound::Unbounded, &options)
+            .unwrap(),
+        (2000..2100)
+            .map(|idx| (key_of(idx).len() + value_of(idx, 0).len()) as u64)
+            .sum::<u64>()
+    );
+    assert_eq!(
+        storage
+            .approximate_count(Bound::Unbounded, Bound::Unbounded, &options)
+            .unwrap(),
+        2100
+    );
+}
@@ -0,0 +1,294 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::backup::BackupEngine;
+use crate::env::{write_file, Env, FaultInjectionEnv, MemEnv, RandomAccessFile, WritableFile};
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{dump, key_of, value_of, Model};
+
+fn options(env: Arc<dyn Env>) -> LsmStorageOptions {
+    LsmStorageOptions {
+        block_size: 128,
+        blob_threshold: Some(64),
+        env,
+        ..Default::default()
+    }
+}
+
+/// Overwrite 20 keys with a new version, leaving them in the memtable.
+fn write_version(storage: &LsmStorage, version: usize) {
+    for idx in version * 10..version * 10 + 20 {
+        storage.put(&key_of(idx), &value_of(idx, version)).unwrap();
+    }
+    storage.delete(&key_of(version)).unwrap();
+}
+
+fn restore(engine: &BackupEngine, env: &MemEnv, id: usize, dir: &str) -> Model {
+    engine.restore_backup(id, dir).unwrap();
+    let storage = LsmStorage::open_with_options(dir, options(Arc::new(env.clone()))).unwrap();
+    dump(&storage)
+}
+
+#[test]
+fn test_incremental_backups() {
+    let env = MemEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+    let shared = Path::new("/backup/shared");
+
+    let mut expected = Vec::new();
+    for version in 0..4 {
+        write_version(&storage, version);
+        let shared_before = env.list(shared).unwrap();
+        let info = engine.create_backup(&storage).unwrap();
+        assert_eq!(info.id, version + 1);
+        expected.push(dump(&storage));
+
+        // Only the files of the new flush are copied, the others are shared with older backups.
+        let shared_after = env.list(shared).unwrap();
+        assert!(shared_before.iter().all(|path| shared_after.contains(path)));
+        assert_eq!(shared_after.len() - shared_before.len(), 2);
+        assert_eq!(info.num_files, shared_after.len());
+        let total_size: u64 = shared_after
+            .iter()
+            .map(|path| env.open(path).unwrap().size())
+            .sum();
+        assert_eq!(info.size, total_size);
+    }
+    assert_eq!(
+        engine
+            .list_backups()
+            .unwrap()
+            .iter()
+            .map(|info| info.id)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+
+    // The backups survive the removal of files in the database.
+    storage.gc_blob_files(0.0).unwrap();
+    for (idx, expected) in expected.iter().enumerate() {
+        engine.verify_backup(idx + 1).unwrap();
+        assert_eq!(
+            &restore(&engine, &env, idx + 1, &format!("/restore{}", idx)),
+            expected
+        );
+    }
+    // Restoring needs an empty directory.
+    assert!(engine.restore_backup(1, "/restore0").is_err());
+    assert!(engine.restore_backup(5, "/restore5").is_err());
+}
+
+#[test]
+fn test_purge_old_backups() {
+    let env = MemEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+    let mut expected = Vec::new();
+    for version in 0..5 {
+        write_version(&storage, version);
+        engine.create_backup(&storage).unwrap();
+        expected.push(dump(&storage));
+        // Files removed from the database are only kept by the backups.
+        storage.gc_blob_files(0.3).unwrap();
+    }
+
+    assert_eq!(engine.purge_old_backups(2).unwrap(), 3);
+    assert_eq!(engine.purge_old_backups(2).unwrap(), 0);
+    let infos = engine.list_backups().unwrap();
+    assert_eq!(infos.iter().map(|info| info.id).collect::<Vec<_>>(), [4, 5]);
+    // Only the files of the remaining backups are kept.
+    let num_files = env.list(Path::new("/backup/shared")).unwrap().len();
+    assert!(num_files < infos[0].num_files + infos[1].num_files);
+    assert_eq!(restore(&engine, &env, 4, "/restore4"), expected[3]);
+    assert!(engine.restore_backup(3, "/restore3").is_err());
+
+    engine.delete_backup(5).unwrap();
+    assert!(engine.delete_backup(5).is_err());
+    assert_eq!(
+        env.list(Path::new("/backup/shared")).unwrap().len(),
+        infos[0].num_files
+    );
+    assert_eq!(restore(&engine, &env, 4, "/restore_again"), expected[3]);
+
+    // New backups get new ids.
+    assert_eq!(engine.create_backup(&storage).unwrap().id, 5);
+}
+
+#[test]
+fn test_backup_corruption() {
+    let env = MemEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+    write_version(&storage, 0);
+    engine.create_backup(&storage).unwrap();
+    engine.verify_backup(1).unwrap();
+
+    let path = env.list(Path::new("/backup/shared")).unwrap()[0].clone();
+    let file = env.open(&path).unwrap();
+    let mut data = file.read_at(0, file.size()).unwrap().to_vec();
+    data[0] ^= 1;
+    write_file(&env, &path, &data).unwrap();
+    assert!(engine.verify_backup(1).is_err());
+    assert!(engine.restore_backup(1, "/restore").is_err());
+}
+
+#[test]
+fn test_interrupted_backup() {
+    let env = FaultInjectionEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+    write_version(&storage, 0);
+    engine.create_backup(&storage).unwrap();
+    let expected = dump(&storage);
+    let shared = Path::new("/backup/shared");
+    let num_files = env.list(shared).unwrap().len();
+
+    // Fail the second backup at every possible point, the first one stays intact.
+    write_version(&storage, 1);
+    storage.sync().unwrap();
+    for ops in 0.. {
+        env.fail_after(ops);
+        let result = engine.create_backup(&storage);
+        env.clear_failures();
+        if result.is_ok() {
+            break;
+        }
+        let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+        engine.verify_backup(1).unwrap();
+        // A failure of the last directory sync leaves a complete backup.
+        if engine.list_backups().unwrap().len() == 2 {
+            engine.verify_backup(2).unwrap();
+            engine.delete_backup(2).unwrap();
+        }
+        assert_eq!(engine.list_backups().unwrap().len(), 1);
+        assert_eq!(env.list(shared).unwrap().len(), num_files);
+    }
+    assert_eq!(engine.list_backups().unwrap().len(), 2);
+
+    engine.restore_backup(1, "/restore").unwrap();
+    let restored =
+        LsmStorage::open_with_options("/restore", options(Arc::new(env.clone()))).unwrap();
+    assert_eq!(dump(&restored), expected);
+}
+
+#[test]
+fn test_backup_of_restored_database() {
+    let env = MemEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let mut engine = BackupEngine::open(Arc::new(env.clone()), "/backup").unwrap();
+    write_version(&storage, 0);
+    engine.create_backup(&storage).unwrap();
+    write_version(&storage, 1);
+    engine.create_backup(&storage).unwrap();
+    let expected = dump(&storage);
+
+    // The restored database reuses the ids of the files of the second backup for other content.
+    let restored = {
+        engine.restore_backup(1, "/restore").unwrap();
+        LsmStorage::open_with_options("/restore", options(Arc::new(env.clone()))).unwrap()
+    };
+    write_version(&restored, 2);
+    let info = engine.create_backup(&restored).unwrap();
+    assert_eq!(info.id, 3);
+    let restored_expected = dump(&restored);
+    drop(restored);
+
+    for (id, expected) in [(2, &expected), (3, &restored_expected)] {
+        engine.verify_backup(id).unwrap();
+        assert_eq!(
+            &restore(&engine, &env, id, &format!("/restore{}", id)),
+            expected
+        );
+    }
+}
+
+/// An environment whose file creations wait until the gate is opened.
+#[derive(Debug, Default)]
+struct GatedEnv {
+    inner: MemEnv,
+    waiting: AtomicBool,
+    gate: (Mutex<bool>, Condvar),
+}
+
+impl GatedEnv {
+    fn open_gate(&self) {
+        *self.gate.0.lock().unwrap() = true;
+        self.gate.1.notify_all();
+    }
+}
+
+impl Env for GatedEnv {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        self.waiting.store(true, Ordering::SeqCst);
+        let mut open = self.gate.0.lock().unwrap();
+        while !*open {
+            open = self.gate.1.wait(open).unwrap();
+        }
+        self.inner.create(path)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn RandomAccessFile>> {
+        self.inner.open(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.inner.delete(path)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        self.inner.list(dir)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> Result<()> {
+        self.inner.create_dir_all(dir)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        self.inner.sync_dir(dir)
+    }
+}
+
+#[test]
+fn test_sync_during_backup() {
+    let env = MemEnv::new();
+    let storage = LsmStorage::open_with_options("/db", options(Arc::new(env.clone()))).unwrap();
+    let backup_env = Arc::new(GatedEnv::default());
+    let mut engine = BackupEngine::open(backup_env.clone(), "/backup").unwrap();
+    write_version(&storage, 0);
+
+    let (expected, synced) = std::thread::scope(|scope| {
+        let backup = scope.spawn(|| engine.create_backup(&storage));
+        while !backup_env.waiting.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // The backup is copying its files, the storage can flush meanwhile.
+        let expected = dump(&storage);
+        write_version(&storage, 1);
+        let (tx, rx) = mpsc::channel();
+        let storage = &storage;
+        scope.spawn(move || {
+            storage.sync().unwrap();
+            tx.send(()).unwrap();
+        });
+        let synced = rx.recv_timeout(Duration::from_secs(5)).is_ok();
+        backup_env.open_gate();
+        backup.join().unwrap().unwrap();
+        (expected, synced)
+    });
+    assert!(synced);
+    engine.verify_backup(1).unwrap();
+    engine.restore_backup(1, "/restore").unwrap();
+    let restored =
+        LsmStorage::open_with_options("/restore", options(Arc::new(backup_env.inner.clone())))
+            .unwrap();
+    assert_eq!(dump(&restored), expected);
+}
@@ -0,0 +1,173 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::blob::{BlobPointer, StoredValue};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+fn open_with_blob_threshold(dir: &tempfile::TempDir, threshold: usize) -> LsmStorage {
+    LsmStorage::open_with_options(
+        dir,
+        LsmStorageOptions {
+            blob_threshold: Some(threshold),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn large_value(idx: usize) -> Vec<u8> {
+    format!("{:0>1024}", idx).into_bytes()
+}
+
+fn num_of_blob_files(dir: &tempfile::TempDir) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("blob".as_ref()))
+        .count()
+}
+
+#[test]
+fn test_stored_value_encode_decode() {
+    let pointer = BlobPointer {
+        file_id: 3,
+        offset: 233,
+        len: 2333,
+    };
+    let mut buf = Vec::new();
+    StoredValue::Blob(pointer).encode(&mut buf);
+    assert_eq!(
+        StoredValue::decode(&buf).unwrap(),
+        StoredValue::Blob(pointer)
+    );
+    buf.clear();
+    StoredValue::Inline(b"233").encode(&mut buf);
+    assert_eq!(
+        StoredValue::decode(&buf).unwrap(),
+        StoredValue::Inline(b"233")
+    );
+    assert!(StoredValue::decode(b"").is_err());
+}
+
+#[test]
+fn test_blob_get_and_scan() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_blob_threshold(&dir, 64);
+    storage.put(b"1", &large_value(1)).unwrap();
+    storage.put(b"2", b"small").unwrap();
+    storage.put(b"3", &large_value(3)).unwrap();
+    storage.sync().unwrap();
+    assert_eq!(num_of_blob_files(&dir), 1);
+    storage.delete(b"3").unwrap();
+
+    assert_eq!(
+        &storage.get(b"1").unwrap().unwrap()[..],
+        &large_value(1)[..]
+    );
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"small");
+    assert!(storage.get(b"3").unwrap().is_none());
+    assert!(storage.get(b"4").unwrap().is_none());
+
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    assert_eq!(
+        result,
+        vec![
+            (Bytes::from("1"), Bytes::from(large_value(1))),
+            (Bytes::from("2"), Bytes::from("small")),
+        ]
+    );
+}
+
+#[test]
+fn test_blob_gc() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_blob_threshold(&dir, 64);
+    for i in 0..10 {
+        storage
+            .put(format!("key_{}", i).as_bytes(), &large_value(i))
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    // Nothing to collect yet.
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 0);
+
+    for i in 0..8 {
+        storage
+            .put(
+                format!("key_{}", i).as_bytes(),
+                format!("v{}", i).as_bytes(),
+            )
+            .unwrap();
+    }
+    storage.delete(b"key_8").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 1);
+    // The live value has been moved to a new blob file.
+    assert_eq!(num_of_blob_files(&dir), 1);
+
+    for i in 0..8 {
+        assert_eq!(
+            &storage
+                .get(format!("key_{}", i).as_bytes())
+                .unwrap()
+                .unwrap()[..],
+            format!("v{}", i).as_bytes()
+        );
+    }
+    assert!(storage.get(b"key_8").unwrap().is_none());
+    assert_eq!(
+        &storage.get(b"key_9").unwrap().unwrap()[..],
+        &large_value(9)[..]
+    );
+
+    storage.delete(b"key_9").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 1);
+    assert_eq!(num_of_blob_files(&dir), 0);
+    assert!(storage.get(b"key_9").unwrap().is_none());
+}
+
+#[test]
+fn test_blob_gc_concurrent_writes() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_blob_threshold(&dir, 64);
+    let key_of = |idx: usize| format!("key_{:03}", idx).into_bytes();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &large_value(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+
+    // Values written back by the collection must never replace a newer write.
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(|| {
+            for round in 1..=20 {
+                for idx in 0..100 {
+                    storage
+                        .put(&key_of(idx), &large_value(round * 1000 + idx))
+                        .unwrap();
+                }
+                storage.sync().unwrap();
+            }
+        });
+        while !writer.is_finished() {
+            storage.gc_blob_files(0.0).unwrap();
+        }
+    });
+    storage.gc_blob_files(0.0).unwrap();
+    for idx in 0..100 {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap().unwrap(),
+            large_value(20 * 1000 + idx)
+        );
+    }
+}
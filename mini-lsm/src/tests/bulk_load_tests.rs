@@ -0,0 +1,127 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{BulkLoadOptions, LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{key_of, value_of};
+
+const NUM_KEYS: usize = 5000;
+
+/// All keys in a scrambled order with version 0, then every third key again with version 1.
+fn unsorted_pairs() -> impl Iterator<Item = (Bytes, Bytes)> {
+    let scrambled = (0..NUM_KEYS).map(|idx| idx * 7919 % NUM_KEYS);
+    let overwritten = scrambled.clone().filter(|idx| idx.is_multiple_of(3));
+    scrambled
+        .map(|idx| (key_of(idx), value_of(idx, 0)))
+        .chain(overwritten.map(|idx| (key_of(idx), value_of(idx, 1))))
+}
+
+fn expected_value(idx: usize) -> Bytes {
+    value_of(idx, usize::from(idx.is_multiple_of(3)))
+}
+
+fn small_options() -> BulkLoadOptions {
+    BulkLoadOptions {
+        memory_limit: 16 << 10,
+        target_sst_size: 8 << 10,
+    }
+}
+
+fn check_all(storage: &LsmStorage) {
+    let mut iter = storage
+        .scan(Bound::Unbounded, Bound::Excluded(&key_of(NUM_KEYS)))
+        .unwrap();
+    for idx in 0..NUM_KEYS {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), expected_value(idx));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+    for idx in (0..NUM_KEYS).step_by(37) {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(expected_value(idx))
+        );
+    }
+}
+
+#[test]
+fn test_bulk_load_into_bottom_level() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            block_size: 256,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage
+        .bulk_load(unsorted_pairs(), &small_options())
+        .unwrap();
+
+    {
+        let snapshot = storage.inner.read();
+        assert!(snapshot.l0_sstables.is_empty());
+        assert!(snapshot.memtable.is_empty());
+        assert_eq!(snapshot.levels.len(), 6);
+        assert!(snapshot.levels[..5].iter().all(Vec::is_empty));
+        let bottom = &snapshot.levels[5];
+        assert!(bottom.len() > 1);
+        for pair in bottom.windows(2) {
+            assert!(pair[0].last_key() < pair[1].first_key());
+        }
+        let num_entries: u64 = bottom
+            .iter()
+            .map(|table| table.properties().num_entries)
+            .sum();
+        assert_eq!(num_entries, NUM_KEYS as u64);
+        // The temporary runs are removed, only the SSTs and the manifest are left.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), bottom.len() + 1);
+    }
+    check_all(&storage);
+
+    drop(storage);
+    let storage = LsmStorage::open(&dir).unwrap();
+    check_all(&storage);
+}
+
+#[test]
+fn test_bulk_load_is_newer_than_existing_data() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    for idx in (0..NUM_KEYS).step_by(100) {
+        storage.put(&key_of(idx), b"flushed").unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in (50..NUM_KEYS).step_by(100) {
+        storage.put(&key_of(idx), b"unflushed").unwrap();
+    }
+    storage.put(b"zzz", b"untouched").unwrap();
+    storage
+        .bulk_load(unsorted_pairs(), &small_options())
+        .unwrap();
+    check_all(&storage);
+    assert_eq!(storage.get(b"zzz").unwrap(), Some(Bytes::from("untouched")));
+    // The loaded SSTs overlap L0, so they are placed in L0 above the flushed memtable.
+    assert!(storage.inner.read().l0_sstables.len() > 2);
+}
+
+#[test]
+fn test_bulk_load_empty_and_invalid() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    storage
+        .bulk_load(std::iter::empty::<(Bytes, Bytes)>(), &small_options())
+        .unwrap();
+    // Only the manifest is in the directory.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let pairs = unsorted_pairs().chain([(key_of(0), Bytes::new())]);
+    assert!(storage.bulk_load(pairs, &small_options()).is_err());
+    assert_eq!(storage.get(&key_of(1)).unwrap(), None);
+    // The runs spilled before the error are removed.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+}
@@ -0,0 +1,106 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::env::FaultInjectionEnv;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{dump, key_of, value_of};
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions {
+        block_size: 128,
+        blob_threshold: Some(64),
+        ..Default::default()
+    }
+}
+
+/// Write 3 flushed batches and leave a 4th one in the memtable.
+fn write_batches(storage: &LsmStorage) {
+    for version in 0..4 {
+        for idx in version * 10..version * 10 + 30 {
+            storage.put(&key_of(idx), &value_of(idx, version)).unwrap();
+        }
+        storage.delete(&key_of(version)).unwrap();
+        if version < 3 {
+            storage.sync().unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_checkpoint() {
+    let dir = tempdir().unwrap();
+    let checkpoint_dir = dir.path().join("checkpoint");
+    let storage = LsmStorage::open_with_options(dir.path().join("db"), options()).unwrap();
+    write_batches(&storage);
+    let expected = dump(&storage);
+    storage.checkpoint(&checkpoint_dir).unwrap();
+
+    // The SSTs and blob files are shared with the storage.
+    for path in storage.env().list(&checkpoint_dir).unwrap() {
+        let name = path.file_name().unwrap();
+        if name == "MANIFEST" {
+            continue;
+        }
+        let source = storage.path().join(name);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().ino(),
+            std::fs::metadata(&source).unwrap().ino()
+        );
+    }
+
+    // Changes to the storage after the checkpoint, including removed files, do not affect it.
+    for idx in 0..60 {
+        storage.put(&key_of(idx), &value_of(idx, 9)).unwrap();
+    }
+    storage.sync().unwrap();
+    assert!(storage.gc_blob_files(0.5).unwrap() > 0);
+    let checkpoint = LsmStorage::open_with_options(&checkpoint_dir, options()).unwrap();
+    assert_eq!(dump(&checkpoint), expected);
+    for idx in 0..60 {
+        assert_eq!(
+            checkpoint.get(&key_of(idx)).unwrap(),
+            expected.get(&key_of(idx)).cloned()
+        );
+    }
+
+    // The checkpoint is a database of its own, whose writes do not reach the storage.
+    checkpoint.put(b"new", b"value").unwrap();
+    checkpoint.sync().unwrap();
+    drop(checkpoint);
+    let checkpoint = LsmStorage::open_with_options(&checkpoint_dir, options()).unwrap();
+    assert_eq!(checkpoint.get(b"new").unwrap(), Some(Bytes::from("value")));
+    assert_eq!(storage.get(b"new").unwrap(), None);
+}
+
+#[test]
+fn test_checkpoint_requires_empty_dir() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(dir.path().join("db"), options()).unwrap();
+    write_batches(&storage);
+    assert!(storage.checkpoint(storage.path()).is_err());
+    storage.checkpoint(dir.path().join("checkpoint")).unwrap();
+    assert!(storage.checkpoint(dir.path().join("checkpoint")).is_err());
+}
+
+#[test]
+fn test_checkpoint_survives_power_loss() {
+    let env = FaultInjectionEnv::new();
+    let options = LsmStorageOptions {
+        env: Arc::new(env.clone()),
+        ..options()
+    };
+    let storage = LsmStorage::open_with_options("/db", options.clone()).unwrap();
+    write_batches(&storage);
+    let expected = dump(&storage);
+    storage.checkpoint("/checkpoint").unwrap();
+    env.simulate_power_loss();
+    drop(storage);
+
+    let checkpoint = LsmStorage::open_with_options("/checkpoint", options).unwrap();
+    assert_eq!(dump(&checkpoint), expected);
+    assert!(!Path::new("/checkpoint").exists());
+}
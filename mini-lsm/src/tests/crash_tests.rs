@@ -0,0 +1,264 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::env::{write_file, Env, FaultInjectionEnv};
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::manifest::MANIFEST_NAME;
+use crate::tests::harness::{dump, key_of, value_of, Model};
+
+const DB_PATH: &str = "/db";
+
+fn open(env: &FaultInjectionEnv) -> anyhow::Result<LsmStorage> {
+    LsmStorage::open_with_options(
+        DB_PATH,
+        LsmStorageOptions {
+            block_size: 128,
+            blob_threshold: Some(64),
+            env: Arc::new(env.clone()),
+            ..Default::default()
+        },
+    )
+}
+
+/// Write a batch to both the storage and the model: keys in `range` are put with `version`, every
+/// third key of the range is deleted instead.
+fn write_batch(
+    storage: &LsmStorage,
+    model: &mut Model,
+    range: std::ops::Range<usize>,
+    version: usize,
+) {
+    for idx in range {
+        if idx % 3 == 0 && version > 0 {
+            storage.delete(&key_of(idx)).unwrap();
+            model.remove(&key_of(idx));
+        } else {
+            storage.put(&key_of(idx), &value_of(idx, version)).unwrap();
+            model.insert(key_of(idx), value_of(idx, version));
+        }
+    }
+}
+
+/// Check that the storage holds exactly the content of the model, through both `get` and `scan`.
+fn check(storage: &LsmStorage, model: &Model) {
+    assert_eq!(&dump(storage), model);
+    for idx in 0..100 {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap().as_ref(),
+            model.get(&key_of(idx))
+        );
+    }
+}
+
+#[test]
+fn test_reopen() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        blob_threshold: Some(64),
+        ..Default::default()
+    };
+    let mut model = Model::new();
+    {
+        let storage = LsmStorage::open_with_options(&dir, options.clone()).unwrap();
+        write_batch(&storage, &mut model, 0..50, 0);
+        storage.sync().unwrap();
+        write_batch(&storage, &mut model, 25..75, 1);
+        storage.sync().unwrap();
+    }
+    let storage = LsmStorage::open_with_options(&dir, options.clone()).unwrap();
+    check(&storage, &model);
+    // New files do not clobber the recovered ones.
+    write_batch(&storage, &mut model, 50..100, 2);
+    storage.sync().unwrap();
+    drop(storage);
+    let storage = LsmStorage::open_with_options(&dir, options).unwrap();
+    check(&storage, &model);
+}
+
+#[test]
+fn test_power_loss_keeps_synced_writes() {
+    let env = FaultInjectionEnv::new();
+    let mut model = Model::new();
+    {
+        let storage = open(&env).unwrap();
+        write_batch(&storage, &mut model, 0..50, 0);
+        storage.sync().unwrap();
+        // Not acknowledged by a sync, lost on power loss.
+        write_batch(&storage, &mut model.clone(), 25..75, 1);
+    }
+    env.simulate_power_loss();
+    check(&open(&env).unwrap(), &model);
+}
+
+/// Crash at every I/O call of a `sync`, and check that the storage recovers either the state
+/// before the sync or, if the sync may have completed, the state after it.
+fn test_crash_during_sync(torn_writes: bool) {
+    for crash_point in 0.. {
+        let env = FaultInjectionEnv::new();
+        let mut before = Model::new();
+        let storage = open(&env).unwrap();
+        write_batch(&storage, &mut before, 0..60, 0);
+        storage.sync().unwrap();
+        let mut after = before.clone();
+        write_batch(&storage, &mut after, 30..90, 1);
+
+        env.fail_after(crash_point);
+        let result = storage.sync();
+        env.clear_failures();
+        drop(storage);
+        if torn_writes {
+            env.simulate_power_loss_with_torn_writes(crash_point as u64);
+        } else {
+            env.simulate_power_loss();
+        }
+
+        let storage = open(&env).unwrap();
+        let recovered = dump(&storage);
+        if result.is_ok() {
+            check(&storage, &after);
+            break;
+        }
+        assert!(
+            recovered == before || recovered == after,
+            "inconsistent state after crash at I/O call {}",
+            crash_point
+        );
+        // The recovered storage keeps working, and cleaned up the files of the failed sync.
+        let expected = if recovered == before { before } else { after };
+        let mut model = expected.clone();
+        write_batch(&storage, &mut model, 80..100, 2);
+        storage.sync().unwrap();
+        drop(storage);
+        env.simulate_power_loss();
+        check(&open(&env).unwrap(), &model);
+    }
+}
+
+#[test]
+fn test_crash_during_sync_drops_unsynced_data() {
+    test_crash_during_sync(false);
+}
+
+#[test]
+fn test_crash_during_sync_with_torn_writes() {
+    test_crash_during_sync(true);
+}
+
+/// Fail at every I/O call of a `sync` while the process keeps running: the memtable of the failed
+/// sync must be persisted by the next successful one.
+#[test]
+fn test_sync_after_failed_sync() {
+    for fail_point in 0.. {
+        let env = FaultInjectionEnv::new();
+        let mut model = Model::new();
+        let storage = open(&env).unwrap();
+        write_batch(&storage, &mut model, 0..60, 0);
+
+        env.fail_after(fail_point);
+        let result = storage.sync();
+        env.clear_failures();
+        write_batch(&storage, &mut model, 30..90, 1);
+        check(&storage, &model);
+        storage.sync().unwrap();
+        assert!(storage.inner.read().imm_memtables.is_empty());
+
+        drop(storage);
+        env.simulate_power_loss();
+        check(&open(&env).unwrap(), &model);
+        if result.is_ok() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_crash_during_blob_gc() {
+    for crash_point in 0.. {
+        let env = FaultInjectionEnv::new();
+        let mut model = Model::new();
+        let storage = open(&env).unwrap();
+        write_batch(&storage, &mut model, 0..60, 0);
+        storage.sync().unwrap();
+        write_batch(&storage, &mut model, 0..40, 1);
+        storage.sync().unwrap();
+
+        env.fail_after(crash_point);
+        let result = storage.gc_blob_files(0.5);
+        env.clear_failures();
+        drop(storage);
+        env.simulate_power_loss_with_torn_writes(crash_point as u64);
+
+        // The garbage collection does not change the content, whether it completed or not.
+        let storage = open(&env).unwrap();
+        check(&storage, &model);
+        if let Ok(removed) = result {
+            assert_eq!(removed, 1);
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_blob_gc_with_unsynced_overwrite() {
+    let env = FaultInjectionEnv::new();
+    let mut model = Model::new();
+    let storage = open(&env).unwrap();
+    write_batch(&storage, &mut model, 1..2, 0);
+    storage.sync().unwrap();
+    // The newer version is only in the memtable when the garbage collection finds the old value
+    // dead.
+    write_batch(&storage, &mut model, 1..2, 1);
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 1);
+    drop(storage);
+    env.simulate_power_loss();
+
+    let storage = open(&env).unwrap();
+    check(&storage, &model);
+}
+
+#[test]
+fn test_orphan_files_removed() {
+    let env = FaultInjectionEnv::new();
+    let mut model = Model::new();
+    let storage = open(&env).unwrap();
+    write_batch(&storage, &mut model, 0..50, 0);
+    storage.sync().unwrap();
+    let files = env.list(Path::new(DB_PATH)).unwrap();
+    write_batch(&storage, &mut model.clone(), 0..50, 1);
+    // Fail the manifest update: writing the blob file and the SST takes 4 I/O calls each.
+    env.fail_after(8);
+    assert!(storage.sync().is_err());
+    env.clear_failures();
+    assert!(env.list(Path::new(DB_PATH)).unwrap().len() > files.len());
+    drop(storage);
+
+    let storage = open(&env).unwrap();
+    assert_eq!(env.list(Path::new(DB_PATH)).unwrap(), files);
+    check(&storage, &model);
+}
+
+#[test]
+fn test_open_without_manifest() {
+    let env = FaultInjectionEnv::new();
+    let mut model = Model::new();
+    let storage = open(&env).unwrap();
+    write_batch(&storage, &mut model, 0..50, 0);
+    storage.sync().unwrap();
+    drop(storage);
+    let files = env.list(Path::new(DB_PATH)).unwrap();
+
+    // The tables are kept when the manifest is lost.
+    let manifest = Path::new(DB_PATH).join(MANIFEST_NAME);
+    let file = env.open(&manifest).unwrap();
+    let data = file.read_at(0, file.size()).unwrap();
+    env.delete(&manifest).unwrap();
+    assert!(open(&env).is_err());
+    assert_eq!(env.list(Path::new(DB_PATH)).unwrap().len(), files.len() - 1);
+
+    write_file(&env, &manifest, &data).unwrap();
+    let storage = open(&env).unwrap();
+    assert_eq!(env.list(Path::new(DB_PATH)).unwrap(), files);
+    check(&storage, &model);
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::env::{write_file, FaultInjectionEnv, PosixEnv};
+use crate::event_listener::{
+    BackgroundErrorReason, BlobFileDeletionInfo, EventListener, FlushJobInfo,
+    TableFileCreationInfo, TableFileCreationReason, TableFileDeletionInfo,
+};
+use crate::lsm_storage::{BulkLoadOptions, LsmStorage, LsmStorageOptions, NUM_LEVELS};
+use crate::table::SstFileWriter;
+use crate::tests::harness::key_of;
+
+#[derive(Debug)]
+enum Event {
+    Flush(FlushJobInfo),
+    Created(TableFileCreationInfo),
+    Deleted(TableFileDeletionInfo),
+    BlobDeleted(BlobFileDeletionInfo),
+    Error(BackgroundErrorReason, String),
+}
+
+#[derive(Debug, Default)]
+struct Recorder(Mutex<Vec<Event>>);
+
+impl Recorder {
+    fn take(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.0.lock())
+    }
+}
+
+impl EventListener for Recorder {
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        self.0.lock().push(Event::Flush(info.clone()));
+    }
+
+    fn on_table_file_created(&self, info: &TableFileCreationInfo) {
+        self.0.lock().push(Event::Created(info.clone()));
+    }
+
+    fn on_table_file_deleted(&self, info: &TableFileDeletionInfo) {
+        self.0.lock().push(Event::Deleted(info.clone()));
+    }
+
+    fn on_blob_file_deleted(&self, info: &BlobFileDeletionInfo) {
+        self.0.lock().push(Event::BlobDeleted(info.clone()));
+    }
+
+    fn on_background_error(&self, reason: BackgroundErrorReason, error: &anyhow::Error) {
+        self.0.lock().push(Event::Error(reason, error.to_string()));
+    }
+}
+
+fn options(recorder: &Arc<Recorder>) -> LsmStorageOptions {
+    LsmStorageOptions {
+        blob_threshold: Some(64),
+        listeners: vec![recorder.clone()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_flush_events() {
+    let dir = tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let storage = LsmStorage::open_with_options(&dir, options(&recorder)).unwrap();
+    // An empty memtable is not flushed.
+    storage.sync().unwrap();
+    assert!(recorder.take().is_empty());
+
+    for idx in 0..10 {
+        storage.put(&key_of(idx), b"value").unwrap();
+    }
+    storage.put(b"large", &[b'x'; 100]).unwrap();
+    storage.delete(&key_of(0)).unwrap();
+    storage.sync().unwrap();
+    let events = recorder.take();
+    assert_eq!(events.len(), 2, "{:?}", events);
+    let Event::Created(ref created) = events[0] else {
+        panic!("unexpected event {:?}", events[0]);
+    };
+    let Event::Flush(ref flush) = events[1] else {
+        panic!("unexpected event {:?}", events[1]);
+    };
+    let snapshot = storage.inner.read().clone();
+    let table = &snapshot.l0_sstables[0];
+    assert_eq!(created.sst_id, table.sst_id());
+    assert_eq!(
+        created.path,
+        dir.path().join(format!("{:05}.sst", table.sst_id()))
+    );
+    assert_eq!(created.level, 0);
+    assert_eq!(created.file_size, table.file_size());
+    assert_eq!(created.num_entries, 11);
+    assert_eq!(created.reason, TableFileCreationReason::Flush);
+    assert_eq!(flush.sst_id, table.sst_id());
+    assert_eq!(flush.num_entries, 11);
+    let blob_file = snapshot.blob_files.values().next().unwrap();
+    assert_eq!(flush.blob_file_id, Some(blob_file.id()));
+    assert_eq!(flush.bytes_written, table.file_size() + blob_file.size());
+}
+
+#[test]
+fn test_flush_error_event() {
+    let env = FaultInjectionEnv::new();
+    let recorder = Arc::new(Recorder::default());
+    let storage = LsmStorage::open_with_options(
+        "/db",
+        LsmStorageOptions {
+            env: Arc::new(env.clone()),
+            ..options(&recorder)
+        },
+    )
+    .unwrap();
+    storage.put(b"key", b"value").unwrap();
+    env.fail_after(0);
+    let error = storage.sync().unwrap_err();
+    let events = recorder.take();
+    assert_eq!(events.len(), 1, "{:?}", events);
+    let Event::Error(reason, ref message) = events[0] else {
+        panic!("unexpected event {:?}", events[0]);
+    };
+    assert_eq!(reason, BackgroundErrorReason::Flush);
+    assert_eq!(*message, error.to_string());
+}
+
+#[test]
+fn test_ingest_and_bulk_load_events() {
+    let dir = tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let storage = LsmStorage::open_with_options(dir.path().join("db"), options(&recorder)).unwrap();
+
+    let path = dir.path().join("external.sst");
+    let mut writer = SstFileWriter::create(&LsmStorageOptions::default(), &path);
+    for idx in 0..10 {
+        writer.put(&key_of(idx), b"value").unwrap();
+    }
+    writer.finish().unwrap();
+    storage.ingest_files(&[&path]).unwrap();
+    let events = recorder.take();
+    assert_eq!(events.len(), 1, "{:?}", events);
+    let Event::Created(ref created) = events[0] else {
+        panic!("unexpected event {:?}", events[0]);
+    };
+    assert_eq!(created.level, NUM_LEVELS);
+    assert_eq!(created.num_entries, 10);
+    assert_eq!(created.reason, TableFileCreationReason::Ingest);
+    assert_eq!(
+        created.sst_id,
+        storage.inner.read().levels[NUM_LEVELS - 1][0].sst_id()
+    );
+
+    // The bulk loaded table overlapping the ingested one goes to the level above, the others to
+    // the bottom level. The runs are temporary and not reported.
+    storage
+        .bulk_load(
+            (0..1000).map(|idx| (key_of(idx), Bytes::from_static(b"value"))),
+            &BulkLoadOptions {
+                memory_limit: 4 << 10,
+                target_sst_size: 4 << 10,
+            },
+        )
+        .unwrap();
+    let events = recorder.take();
+    let snapshot = storage.inner.read().clone();
+    let num_tables: usize = snapshot.levels.iter().map(Vec::len).sum();
+    assert_eq!(events.len(), num_tables - 1, "{:?}", events);
+    let mut levels = Vec::new();
+    for event in &events {
+        let Event::Created(created) = event else {
+            panic!("unexpected event {:?}", event);
+        };
+        assert_eq!(created.reason, TableFileCreationReason::BulkLoad);
+        assert!(snapshot.levels[created.level - 1]
+            .iter()
+            .any(|table| table.sst_id() == created.sst_id));
+        levels.push(created.level);
+    }
+    assert_eq!(levels[0], NUM_LEVELS - 1);
+    assert!(levels[1..].iter().all(|level| *level == NUM_LEVELS));
+}
+
+#[test]
+fn test_orphan_deletion_events() {
+    let dir = tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let storage = LsmStorage::open_with_options(&dir, options(&recorder)).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.sync().unwrap();
+    drop(storage);
+    recorder.take();
+
+    let orphan = dir.path().join("00099.sst");
+    let orphan_blob = dir.path().join("00098.blob");
+    write_file(&PosixEnv, &orphan, b"orphan").unwrap();
+    write_file(&PosixEnv, &orphan_blob, b"orphan").unwrap();
+    let storage = LsmStorage::open_with_options(&dir, options(&recorder)).unwrap();
+    let mut events = recorder.take();
+    assert_eq!(events.len(), 2, "{:?}", events);
+    // The order of the files in the directory is not specified.
+    events.sort_by_key(|event| matches!(event, Event::BlobDeleted(_)));
+    let Event::Deleted(ref deleted) = events[0] else {
+        panic!("unexpected event {:?}", events[0]);
+    };
+    assert_eq!(deleted.sst_id, 99);
+    assert_eq!(deleted.path, orphan);
+    assert!(!orphan.exists());
+    let Event::BlobDeleted(ref deleted) = events[1] else {
+        panic!("unexpected event {:?}", events[1]);
+    };
+    assert_eq!(deleted.blob_file_id, 98);
+    assert_eq!(deleted.path, orphan_blob);
+    assert!(!orphan_blob.exists());
+    assert_eq!(&storage.get(b"key").unwrap().unwrap()[..], b"value");
+}
+
+#[test]
+fn test_blob_gc_deletion_events() {
+    let dir = tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let storage = LsmStorage::open_with_options(&dir, options(&recorder)).unwrap();
+    for version in 0..2 {
+        storage.put(b"large", &[version; 100]).unwrap();
+        storage.sync().unwrap();
+    }
+    let blob_file_id = *storage.inner.read().blob_files.keys().min().unwrap();
+    recorder.take();
+
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 1);
+    let events = recorder.take();
+    assert_eq!(events.len(), 1, "{:?}", events);
+    let Event::BlobDeleted(ref deleted) = events[0] else {
+        panic!("unexpected event {:?}", events[0]);
+    };
+    assert_eq!(deleted.blob_file_id, blob_file_id);
+    assert_eq!(deleted.path, storage.path_of_blob(blob_file_id));
+    assert!(!deleted.path.exists());
+}
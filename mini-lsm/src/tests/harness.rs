@@ -0,0 +1,34 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::LsmStorage;
+
+/// The expected content of a storage.
+pub type Model = BTreeMap<Bytes, Bytes>;
+
+pub fn key_of(idx: usize) -> Bytes {
+    Bytes::from(format!("key_{:05}", idx))
+}
+
+pub fn value_of(idx: usize, version: usize) -> Bytes {
+    // Every other value is large enough to go to a blob file.
+    let width = if idx.is_multiple_of(2) { 10 } else { 100 };
+    Bytes::from(format!("value_{}_{:0>width$}", version, idx))
+}
+
+/// Read the whole content of a storage with a scan.
+pub fn dump(storage: &LsmStorage) -> Model {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut result = Model::new();
+    while iter.is_valid() {
+        result.insert(
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        );
+        iter.next().unwrap();
+    }
+    result
+}
@@ -0,0 +1,273 @@
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, Weak};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::{tempdir, TempDir};
+
+use crate::blob::{BlobPointer, StoredValue};
+use crate::env::{write_file, PosixEnv};
+use crate::event_listener::{EventListener, TableFileCreationInfo};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::table::{SsTableBuilder, SstFileReader, SstFileWriter};
+use crate::tests::harness::{key_of, value_of};
+
+/// Write an external SST holding `keys` with values of `version`.
+fn build_sst(
+    dir: &TempDir,
+    name: &str,
+    keys: impl IntoIterator<Item = usize>,
+    version: usize,
+) -> PathBuf {
+    let path = dir.path().join(name);
+    let mut writer = SstFileWriter::create(&LsmStorageOptions::default(), &path);
+    for idx in keys {
+        writer.put(&key_of(idx), &value_of(idx, version)).unwrap();
+    }
+    writer.finish().unwrap();
+    path
+}
+
+/// Get the number of SSTs in L0 and in each level.
+fn shape(storage: &LsmStorage) -> (usize, Vec<usize>) {
+    let snapshot = storage.inner.read();
+    (
+        snapshot.l0_sstables.len(),
+        snapshot.levels.iter().map(Vec::len).collect(),
+    )
+}
+
+fn check_range(storage: &LsmStorage, range: std::ops::Range<usize>, version: usize) {
+    for idx in range.clone() {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(value_of(idx, version))
+        );
+    }
+    let mut iter = storage
+        .scan(
+            Bound::Included(&key_of(range.start)),
+            Bound::Excluded(&key_of(range.end)),
+        )
+        .unwrap();
+    for idx in range {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx, version));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_ingest_into_levels() {
+    let dir = tempdir().unwrap();
+    let external = tempdir().unwrap();
+    let storage = LsmStorage::open(dir.path()).unwrap();
+
+    // Files overlapping nothing go to the last level.
+    let files = [
+        build_sst(&external, "b.sst", 100..200, 0),
+        build_sst(&external, "a.sst", 0..100, 0),
+    ];
+    storage.ingest_files(&files).unwrap();
+    assert_eq!(shape(&storage), (0, vec![0, 0, 0, 0, 0, 2]));
+    check_range(&storage, 0..200, 0);
+    // The external files are left in place.
+    assert!(files.iter().all(|path| path.exists()));
+
+    // A file overlapping the last level goes right above it, and shadows it.
+    storage
+        .ingest_files(&[build_sst(&external, "c.sst", 50..150, 1)])
+        .unwrap();
+    assert_eq!(shape(&storage), (0, vec![0, 0, 0, 0, 1, 2]));
+    check_range(&storage, 0..50, 0);
+    check_range(&storage, 50..150, 1);
+    check_range(&storage, 150..200, 0);
+
+    // A file overlapping nothing is sorted into the last level.
+    storage
+        .ingest_files(&[build_sst(&external, "d.sst", 300..400, 0)])
+        .unwrap();
+    assert_eq!(shape(&storage), (0, vec![0, 0, 0, 0, 1, 3]));
+
+    // Files overlapping L0 go to L0.
+    storage.put(&key_of(120), b"flushed").unwrap();
+    storage.sync().unwrap();
+    storage
+        .ingest_files(&[build_sst(&external, "e.sst", 100..130, 2)])
+        .unwrap();
+    assert_eq!(shape(&storage), (2, vec![0, 0, 0, 0, 1, 3]));
+    check_range(&storage, 100..130, 2);
+
+    // The ingested files survive a reopen.
+    drop(storage);
+    let storage = LsmStorage::open(dir.path()).unwrap();
+    assert_eq!(shape(&storage), (2, vec![0, 0, 0, 0, 1, 3]));
+    check_range(&storage, 0..50, 0);
+    check_range(&storage, 50..100, 1);
+    check_range(&storage, 100..130, 2);
+    check_range(&storage, 130..150, 1);
+    check_range(&storage, 150..200, 0);
+    check_range(&storage, 300..400, 0);
+}
+
+#[test]
+fn test_ingest_flushes_overlapping_memtables() {
+    let dir = tempdir().unwrap();
+    let external = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        dir.path(),
+        LsmStorageOptions {
+            row_cache_capacity: Some(1 << 20),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // A memtable that does not overlap the files is not flushed.
+    storage.put(&key_of(500), b"unflushed").unwrap();
+    storage
+        .ingest_files(&[build_sst(&external, "a.sst", 0..100, 0)])
+        .unwrap();
+    assert_eq!(shape(&storage), (0, vec![0, 0, 0, 0, 0, 1]));
+    assert!(!storage.inner.read().memtable.is_empty());
+
+    // An overlapping memtable is flushed first, and older than the ingested entries.
+    storage.put(&key_of(150), b"old").unwrap();
+    storage.delete(&key_of(160)).unwrap();
+    assert_eq!(storage.get(&key_of(150)).unwrap(), Some(Bytes::from("old")));
+    assert_eq!(storage.get(&key_of(160)).unwrap(), None);
+    storage
+        .ingest_files(&[build_sst(&external, "b.sst", 100..200, 1)])
+        .unwrap();
+    assert_eq!(shape(&storage), (2, vec![0, 0, 0, 0, 0, 1]));
+    // The row cache does not return the values from before the ingestion.
+    check_range(&storage, 100..200, 1);
+    assert_eq!(
+        storage.get(&key_of(500)).unwrap(),
+        Some(Bytes::from("unflushed"))
+    );
+}
+
+/// Reads the first key when an ingested table is created, right after it is installed.
+#[derive(Debug, Default)]
+struct ReadOnCreation {
+    storage: OnceLock<Weak<LsmStorage>>,
+    values: Mutex<Vec<Option<Bytes>>>,
+}
+
+impl EventListener for ReadOnCreation {
+    fn on_table_file_created(&self, _info: &TableFileCreationInfo) {
+        let storage = self.storage.get().unwrap().upgrade().unwrap();
+        self.values.lock().push(storage.get(&key_of(0)).unwrap());
+    }
+}
+
+#[test]
+fn test_ingest_invalidates_row_cache_on_install() {
+    let dir = tempdir().unwrap();
+    let external = tempdir().unwrap();
+    let listener = Arc::new(ReadOnCreation::default());
+    let storage = Arc::new(
+        LsmStorage::open_with_options(
+            dir.path(),
+            LsmStorageOptions {
+                row_cache_capacity: Some(1 << 20),
+                listeners: vec![listener.clone()],
+                ..Default::default()
+            },
+        )
+        .unwrap(),
+    );
+    listener.storage.set(Arc::downgrade(&storage)).unwrap();
+
+    storage
+        .ingest_files(&[build_sst(&external, "a.sst", 0..100, 0)])
+        .unwrap();
+    assert_eq!(storage.get(&key_of(0)).unwrap(), Some(value_of(0, 0)));
+    storage
+        .ingest_files(&[build_sst(&external, "b.sst", 0..100, 1)])
+        .unwrap();
+    // Once the new tables are visible, the row cache does not return the old values.
+    assert_eq!(
+        *listener.values.lock(),
+        vec![Some(value_of(0, 0)), Some(value_of(0, 1))]
+    );
+}
+
+#[test]
+fn test_ingest_invalid_files() {
+    let dir = tempdir().unwrap();
+    let external = tempdir().unwrap();
+    let storage = LsmStorage::open(dir.path()).unwrap();
+    let valid = build_sst(&external, "valid.sst", 0..100, 0);
+
+    let garbage = external.path().join("garbage.sst");
+    write_file(&PosixEnv, &garbage, b"not an sst").unwrap();
+    let unsorted = external.path().join("unsorted.sst");
+    let mut builder = SsTableBuilder::new(128);
+    let mut buf = Vec::new();
+    for idx in (200..300).rev() {
+        buf.clear();
+        StoredValue::Inline(&value_of(idx, 0)).encode(&mut buf);
+        builder.add(&key_of(idx), &buf);
+    }
+    builder.build(0, None, &unsorted).unwrap();
+    let blob = external.path().join("blob.sst");
+    let mut builder = SsTableBuilder::new(128);
+    buf.clear();
+    StoredValue::Blob(BlobPointer {
+        file_id: 1,
+        offset: 0,
+        len: 10,
+    })
+    .encode(&mut buf);
+    builder.add(&key_of(400), &buf);
+    builder.build(0, None, &blob).unwrap();
+    let overlapping = build_sst(&external, "overlapping.sst", 50..150, 0);
+    let missing = external.path().join("missing.sst");
+
+    for invalid in [&garbage, &unsorted, &blob, &overlapping, &missing] {
+        let files: [&Path; 2] = [&valid, invalid];
+        assert!(storage.ingest_files(&files).is_err());
+        // Nothing is ingested.
+        assert_eq!(shape(&storage), (0, vec![]));
+        assert_eq!(storage.get(&key_of(0)).unwrap(), None);
+    }
+    storage.ingest_files(&[&valid]).unwrap();
+    check_range(&storage, 0..100, 0);
+}
+
+#[test]
+fn test_ingest_corrupted_files() {
+    let dir = tempdir().unwrap();
+    let external = tempdir().unwrap();
+    let storage = LsmStorage::open(dir.path()).unwrap();
+    let valid = build_sst(&external, "valid.sst", 0..100, 0);
+    let data = std::fs::read(&valid).unwrap();
+
+    // Overwrite the end of the data, the index and the properties, leaving the footer intact.
+    let corrupted = external.path().join("corrupted.sst");
+    let mut corrupted_data = data.clone();
+    let end = data.len() - 12;
+    corrupted_data[end - 80..end].fill(0xff);
+    write_file(&PosixEnv, &corrupted, &corrupted_data).unwrap();
+    assert!(SstFileReader::open(&corrupted)
+        .and_then(|reader| reader.verify())
+        .is_err());
+    assert!(storage.ingest_files(&[&corrupted]).is_err());
+    assert_eq!(shape(&storage), (0, vec![]));
+
+    // Corruption anywhere must not panic. It may go unnoticed inside a value.
+    for start in (0..data.len()).step_by(16) {
+        let mut corrupted_data = data.clone();
+        let end = (start + 80).min(data.len());
+        corrupted_data[start..end].fill(0xff);
+        write_file(&PosixEnv, &corrupted, &corrupted_data).unwrap();
+        let _ = SstFileReader::open(&corrupted).and_then(|reader| reader.verify());
+    }
+    storage.ingest_files(&[&valid]).unwrap();
+    check_range(&storage, 0..100, 0);
+}
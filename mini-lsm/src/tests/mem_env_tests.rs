@@ -0,0 +1,48 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::env::{Env, MemEnv};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{key_of, value_of};
+
+#[test]
+fn test_storage_on_mem_env() {
+    let env = MemEnv::new();
+    let path = std::path::Path::new("/mem/db");
+    let storage = LsmStorage::open_with_options(
+        path,
+        LsmStorageOptions {
+            blob_threshold: Some(100),
+            env: Arc::new(env.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..30 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        if idx % 10 == 9 {
+            storage.sync().unwrap();
+        }
+    }
+    for idx in 0..20 {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+    assert!(!path.exists());
+    // 4 SSTs, 3 blob files and the manifest.
+    assert_eq!(env.list(path).unwrap().len(), 8);
+
+    assert_eq!(storage.gc_blob_files(0.5).unwrap(), 2);
+    for idx in 0..30 {
+        let expected = (idx >= 20).then(|| value_of(idx, 0));
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), expected);
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for idx in 20..30 {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx, 0));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
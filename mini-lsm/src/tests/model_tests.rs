@@ -0,0 +1,226 @@
+//! Randomized tests comparing the storage against a `BTreeMap` model. Proptest generates sequences
+//! of operations, and shrinks a failing sequence to a minimal one before reporting it.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use proptest::prelude::*;
+
+use crate::env::MemEnv;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::prefix_extractor::{FixedPrefixExtractor, PrefixExtractor};
+
+/// Number of distinct keys, small enough for operations to hit the same keys often.
+const NUM_KEYS: u8 = 40;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Put(u8, Vec<u8>),
+    Delete(u8),
+    Get(u8),
+    MultiGet(Vec<u8>),
+    Scan(Bound<u8>, Bound<u8>),
+    /// Scan the keys starting with `key_N`.
+    ScanPrefix(u8),
+    Sync,
+    Reopen,
+    GcBlobFiles,
+}
+
+fn key_of(idx: u8) -> Bytes {
+    Bytes::from(format!("key_{:02}", idx))
+}
+
+fn bound_strategy() -> impl Strategy<Value = Bound<u8>> {
+    prop_oneof![
+        Just(Bound::Unbounded),
+        (0..NUM_KEYS).prop_map(Bound::Included),
+        (0..NUM_KEYS).prop_map(Bound::Excluded),
+    ]
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        // Values up to 100 bytes, so that some of them go to blob files.
+        8 => (0..NUM_KEYS, prop::collection::vec(any::<u8>(), 1..100))
+            .prop_map(|(key, value)| Op::Put(key, value)),
+        3 => (0..NUM_KEYS).prop_map(Op::Delete),
+        4 => (0..NUM_KEYS).prop_map(Op::Get),
+        2 => prop::collection::vec(0..NUM_KEYS, 0..20).prop_map(Op::MultiGet),
+        3 => (bound_strategy(), bound_strategy()).prop_map(|(lower, upper)| Op::Scan(lower, upper)),
+        2 => (0..NUM_KEYS / 10 + 1).prop_map(Op::ScanPrefix),
+        2 => Just(Op::Sync),
+        1 => Just(Op::Reopen),
+        1 => Just(Op::GcBlobFiles),
+    ]
+}
+
+/// Storage options covering the optional table and cache features.
+fn options_strategy() -> impl Strategy<Value = LsmStorageOptions> {
+    (
+        prop_oneof![Just(64usize), Just(256), Just(4096)],
+        prop::option::of(20usize..60),
+        prop::option::of(Just(32usize)),
+        any::<bool>(),
+        prop::option::of(Just(1024u64)),
+        prop::option::of(1usize..4),
+        prop::option::of(4usize..6),
+    )
+        .prop_map(
+            |(
+                block_size,
+                blob_threshold,
+                index_partition_size,
+                block_hash_index,
+                row_cache_capacity,
+                max_open_files,
+                prefix_len,
+            )| LsmStorageOptions {
+                block_size,
+                blob_threshold,
+                index_partition_size,
+                block_hash_index,
+                row_cache_capacity,
+                max_open_files,
+                prefix_extractor: prefix_len.map(|len| {
+                    Arc::new(FixedPrefixExtractor::new(len)) as Arc<dyn PrefixExtractor>
+                }),
+                ..Default::default()
+            },
+        )
+}
+
+fn in_bounds(key: u8, lower: Bound<u8>, upper: Bound<u8>) -> bool {
+    let above_lower = match lower {
+        Bound::Included(lower) => key >= lower,
+        Bound::Excluded(lower) => key > lower,
+        Bound::Unbounded => true,
+    };
+    let below_upper = match upper {
+        Bound::Included(upper) => key <= upper,
+        Bound::Excluded(upper) => key < upper,
+        Bound::Unbounded => true,
+    };
+    above_lower && below_upper
+}
+
+fn collect(mut iter: impl StorageIterator) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+fn run(options: LsmStorageOptions, ops: Vec<Op>) -> Result<(), TestCaseError> {
+    let env = MemEnv::new();
+    let options = LsmStorageOptions {
+        env: Arc::new(env),
+        ..options
+    };
+    let path = Path::new("/db");
+    let open = || LsmStorage::open_with_options(path, options.clone()).unwrap();
+    let mut storage = open();
+    // The model of the current content, and of the content persisted by the last sync.
+    let mut model = BTreeMap::<u8, Bytes>::new();
+    let mut synced = model.clone();
+
+    for (step, op) in ops.into_iter().enumerate() {
+        match op {
+            Op::Put(key, value) => {
+                storage.put(&key_of(key), &value).unwrap();
+                model.insert(key, Bytes::from(value));
+            }
+            Op::Delete(key) => {
+                storage.delete(&key_of(key)).unwrap();
+                model.remove(&key);
+            }
+            Op::Get(key) => {
+                prop_assert_eq!(
+                    storage.get(&key_of(key)).unwrap(),
+                    model.get(&key).cloned(),
+                    "get at step {}",
+                    step
+                );
+            }
+            Op::MultiGet(indices) => {
+                let keys: Vec<_> = indices.iter().map(|idx| key_of(*idx)).collect();
+                let keys: Vec<_> = keys.iter().map(|key| &key[..]).collect();
+                let expected: Vec<_> = indices.iter().map(|idx| model.get(idx).cloned()).collect();
+                prop_assert_eq!(
+                    storage.multi_get(&keys).unwrap(),
+                    expected,
+                    "multi_get at step {}",
+                    step
+                );
+            }
+            Op::Scan(lower, upper) => {
+                let lower_key = lower.map(key_of);
+                let upper_key = upper.map(key_of);
+                let iter = storage
+                    .scan(
+                        lower_key.as_ref().map(|key| &key[..]),
+                        upper_key.as_ref().map(|key| &key[..]),
+                    )
+                    .unwrap();
+                let expected: Vec<_> = model
+                    .iter()
+                    .filter(|(key, _)| in_bounds(**key, lower, upper))
+                    .map(|(key, value)| (key_of(*key), value.clone()))
+                    .collect();
+                prop_assert_eq!(collect(iter), expected, "scan at step {}", step);
+            }
+            Op::ScanPrefix(tens) => {
+                let iter = storage
+                    .scan_prefix(format!("key_{}", tens).as_bytes())
+                    .unwrap();
+                let expected: Vec<_> = model
+                    .iter()
+                    .filter(|(key, _)| **key / 10 == tens)
+                    .map(|(key, value)| (key_of(*key), value.clone()))
+                    .collect();
+                prop_assert_eq!(collect(iter), expected, "scan_prefix at step {}", step);
+            }
+            Op::Sync => {
+                storage.sync().unwrap();
+                synced = model.clone();
+            }
+            Op::Reopen => {
+                // Data that has not been synced is lost.
+                drop(storage);
+                storage = open();
+                model = synced.clone();
+            }
+            Op::GcBlobFiles => {
+                // Removing a blob file flushes the memtables first.
+                if storage.gc_blob_files(0.5).unwrap() > 0 {
+                    synced = model.clone();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: 64,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn test_storage_matches_model(
+        options in options_strategy(),
+        ops in prop::collection::vec(op_strategy(), 1..200),
+    ) {
+        run(options, ops)?;
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{key_of, value_of};
+
+#[test]
+fn test_multi_get_matches_get() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            block_size: 128,
+            blob_threshold: Some(32),
+            row_cache_capacity: Some(1 << 20),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Two disjoint SSTs, moved to L1.
+    for range in [0..50, 50..100] {
+        for idx in range {
+            storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    {
+        let mut guard = storage.inner.write();
+        let mut snapshot = guard.as_ref().clone();
+        let l0_sstables = std::mem::take(&mut snapshot.l0_sstables);
+        snapshot.levels.push(l0_sstables);
+        *guard = Arc::new(snapshot);
+    }
+    // Overlapping L0 SSTs with overwrites and deletions, then the memtable.
+    for idx in (0..100).step_by(3) {
+        storage.put(&key_of(idx), &value_of(idx, 1)).unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in (0..100).step_by(7) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in (0..100).step_by(5) {
+        storage.put(&key_of(idx), &value_of(idx, 2)).unwrap();
+    }
+    storage.delete(&key_of(11)).unwrap();
+
+    // Unsorted keys, with duplicates and missing keys.
+    let keys: Vec<_> = (0..120)
+        .rev()
+        .chain(0..10)
+        .map(|idx| key_of(idx * 37 % 120))
+        .collect();
+    let keys: Vec<_> = keys.iter().map(|key| &key[..]).collect();
+    let values = storage.multi_get(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(value, &storage.get(key).unwrap(), "key {:?}", key);
+    }
+    // The results are the same when served from the row cache.
+    assert_eq!(storage.multi_get(&keys).unwrap(), values);
+
+    assert_eq!(storage.multi_get(&[]).unwrap(), vec![]);
+}
+
+#[test]
+fn test_multi_get_reads_each_block_once() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            block_size: 128,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.sync().unwrap();
+    let num_of_blocks = {
+        let snapshot = storage.inner.read().clone();
+        storage
+            .open_sst(&snapshot.l0_sstables[0])
+            .unwrap()
+            .num_of_blocks()
+    };
+    assert!(num_of_blocks > 1);
+
+    let keys: Vec<_> = (0..100).rev().map(key_of).collect();
+    let keys: Vec<_> = keys.iter().map(|key| &key[..]).collect();
+    let before = storage.block_cache_stats();
+    let values = storage.multi_get(&keys).unwrap();
+    let after = storage.block_cache_stats();
+    assert_eq!(
+        (after.hits + after.misses - before.hits - before.misses) as usize,
+        num_of_blocks
+    );
+    for (idx, value) in (0..100).rev().zip(values) {
+        assert_eq!(value, Some(value_of(idx, 0)));
+    }
+}
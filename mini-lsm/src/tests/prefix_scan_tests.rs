@@ -0,0 +1,144 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::prefix_extractor::FixedPrefixExtractor;
+
+fn collect(mut iter: impl StorageIterator) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+/// Keys are `user_NN:MM`, with the prefix `user_NN`.
+fn key_of(user: usize, idx: usize) -> Bytes {
+    Bytes::from(format!("user_{:02}:{:02}", user, idx))
+}
+
+fn value_of(user: usize, idx: usize) -> Bytes {
+    Bytes::from(format!("value_{}_{}", user, idx))
+}
+
+/// Write 4 SSTs spanning the same key range, the SST `i` holding the users `u` with `u % 4 == i`.
+fn open_with_sstables(options: LsmStorageOptions) -> (tempfile::TempDir, LsmStorage) {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(&dir, options).unwrap();
+    for sst in 0..4 {
+        for user in (sst..40).step_by(4).chain([40 + sst]) {
+            for idx in 0..5 {
+                storage
+                    .put(&key_of(user, idx), &value_of(user, idx))
+                    .unwrap();
+            }
+        }
+        storage.sync().unwrap();
+    }
+    (dir, storage)
+}
+
+fn expected(user: usize) -> Vec<(Bytes, Bytes)> {
+    (0..5)
+        .map(|idx| (key_of(user, idx), value_of(user, idx)))
+        .collect()
+}
+
+#[test]
+fn test_scan_prefix_skips_sstables() {
+    let (_dir, storage) = open_with_sstables(LsmStorageOptions {
+        prefix_extractor: Some(Arc::new(FixedPrefixExtractor::new(7))),
+        ..Default::default()
+    });
+    let tables = storage.inner.read().l0_sstables.clone();
+    assert_eq!(tables.len(), 4);
+    let close_all = || {
+        for table in &tables {
+            storage.table_cache.invalidate(table.sst_id());
+        }
+    };
+
+    for user in 0..40 {
+        close_all();
+        let prefix = format!("user_{:02}", user);
+        let result = collect(storage.scan_prefix(prefix.as_bytes()).unwrap());
+        assert_eq!(result, expected(user));
+        // Only the SST holding the user is opened, up to false positives of the filters.
+        assert!(storage.table_cache.contains_key(tables[user % 4].sst_id()));
+        assert!(storage.table_cache.entry_count() <= 2);
+    }
+
+    // Missing prefixes open no SST at all, unless the filter has a false positive.
+    let mut opened = 0;
+    for user in 50..90 {
+        close_all();
+        let prefix = format!("user_{:02}", user);
+        assert!(collect(storage.scan_prefix(prefix.as_bytes()).unwrap()).is_empty());
+        opened += storage.table_cache.entry_count();
+    }
+    assert!(opened <= 4, "{} SSTs opened", opened);
+
+    // A prefix longer than the extracted prefix is filtered by its extracted prefix.
+    close_all();
+    let result = collect(storage.scan_prefix(b"user_05:0").unwrap());
+    assert_eq!(result, expected(5));
+    assert!(storage.table_cache.entry_count() <= 2);
+
+    // A prefix shorter than the extracted prefix can only be filtered by the key ranges.
+    close_all();
+    let result = collect(storage.scan_prefix(b"user_0").unwrap());
+    assert_eq!(result.len(), 50);
+    assert_eq!(storage.table_cache.entry_count(), 4);
+}
+
+#[test]
+fn test_scan_prefix_matches_scan() {
+    for prefix_extractor in [None, Some(Arc::new(FixedPrefixExtractor::new(7)) as _)] {
+        let (_dir, storage) = open_with_sstables(LsmStorageOptions {
+            prefix_extractor,
+            ..Default::default()
+        });
+        // Overwrites and deletions in the memtable, and a key right after the range of `user_03`.
+        storage.put(&key_of(3, 1), b"new").unwrap();
+        storage.delete(&key_of(3, 2)).unwrap();
+        storage.put(b"user_04", b"after the prefix").unwrap();
+
+        for prefix in [
+            &b"user_03"[..],
+            b"user_4",
+            b"user_",
+            b"",
+            b"user_99",
+            b"\xff",
+        ] {
+            let upper = crate::prefix_extractor::prefix_upper_bound(prefix);
+            let expected = collect(
+                storage
+                    .scan(
+                        Bound::Included(prefix),
+                        upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+                    )
+                    .unwrap(),
+            );
+            let result = collect(storage.scan_prefix(prefix).unwrap());
+            assert_eq!(
+                result,
+                expected,
+                "prefix {:?}",
+                Bytes::copy_from_slice(prefix)
+            );
+            assert!(result.iter().all(|(key, _)| key.starts_with(prefix)));
+        }
+        let result = collect(storage.scan_prefix(b"user_03").unwrap());
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[1], (key_of(3, 1), Bytes::from("new")));
+    }
+}
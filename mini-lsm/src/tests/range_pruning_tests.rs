@@ -0,0 +1,140 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::blob::StoredValue;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::LsmStorage;
+use crate::table::{SsTableBuilder, SsTableMeta};
+use crate::tests::harness::{key_of, value_of};
+
+fn collect(mut iter: impl StorageIterator) -> Vec<(Bytes, Bytes)> {
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+/// Build a table holding the given keys in the storage format.
+fn build_table(storage: &LsmStorage, keys: impl Iterator<Item = usize>) -> Arc<SsTableMeta> {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in keys {
+        let mut value = Vec::new();
+        StoredValue::Inline(&value_of(idx, 0)).encode(&mut value);
+        builder.add(&key_of(idx), &value);
+    }
+    let id = storage.next_sst_id();
+    storage.add_new_sst(
+        builder
+            .build_with_env(
+                id,
+                Some(storage.block_cache.clone()),
+                storage.env(),
+                storage.path_of_sst(id),
+            )
+            .unwrap(),
+    )
+}
+
+fn block_cached(storage: &LsmStorage, table: &SsTableMeta) -> bool {
+    storage.block_cache.contains_key(&(table.sst_id(), 0))
+}
+
+#[test]
+fn test_sst_key_range() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    let table = build_table(&storage, 10..20);
+    assert_eq!(table.first_key(), &key_of(10));
+    assert_eq!(table.last_key(), &key_of(19));
+    assert!(table.may_contain_key(&key_of(10)));
+    assert!(table.may_contain_key(&key_of(19)));
+    assert!(!table.may_contain_key(&key_of(20)));
+    assert!(table.range_overlap(Bound::Included(&key_of(19)), Bound::Unbounded));
+    assert!(!table.range_overlap(Bound::Excluded(&key_of(19)), Bound::Unbounded));
+    assert!(table.range_overlap(Bound::Unbounded, Bound::Included(&key_of(10))));
+    assert!(!table.range_overlap(Bound::Unbounded, Bound::Excluded(&key_of(10))));
+}
+
+#[test]
+fn test_prune_l0_tables() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    for batch in 0..3 {
+        for idx in batch * 10..batch * 10 + 10 {
+            storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage.block_cache.invalidate_all();
+    let tables = storage.inner.read().l0_sstables.clone();
+
+    assert_eq!(storage.get(&key_of(15)).unwrap(), Some(value_of(15, 0)));
+    assert!(!block_cached(&storage, &tables[0]));
+    assert!(block_cached(&storage, &tables[1]));
+    assert!(!block_cached(&storage, &tables[2]));
+
+    storage.block_cache.invalidate_all();
+    let result = collect(
+        storage
+            .scan(Bound::Excluded(&key_of(9)), Bound::Included(&key_of(20)))
+            .unwrap(),
+    );
+    assert_eq!(
+        result,
+        (10..=20)
+            .map(|idx| (key_of(idx), value_of(idx, 0)))
+            .collect::<Vec<_>>()
+    );
+    assert!(!block_cached(&storage, &tables[0]));
+}
+
+#[test]
+fn test_binary_search_in_levels() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    let level = vec![
+        build_table(&storage, 0..10),
+        build_table(&storage, 10..20),
+        build_table(&storage, 30..40),
+    ];
+    {
+        let mut guard = storage.inner.write();
+        let mut snapshot = guard.as_ref().clone();
+        snapshot.levels.push(level.clone());
+        *guard = Arc::new(snapshot);
+    }
+    storage.put(&key_of(12), b"new").unwrap();
+    storage.delete(&key_of(13)).unwrap();
+
+    assert_eq!(storage.get(&key_of(0)).unwrap(), Some(value_of(0, 0)));
+    assert_eq!(storage.get(&key_of(12)).unwrap(), Some(Bytes::from("new")));
+    assert_eq!(storage.get(&key_of(13)).unwrap(), None);
+    assert_eq!(storage.get(&key_of(19)).unwrap(), Some(value_of(19, 0)));
+    assert_eq!(storage.get(&key_of(25)).unwrap(), None);
+    assert_eq!(storage.get(&key_of(39)).unwrap(), Some(value_of(39, 0)));
+    assert_eq!(storage.get(&key_of(40)).unwrap(), None);
+
+    storage.block_cache.invalidate_all();
+    let result = collect(
+        storage
+            .scan(Bound::Included(&key_of(18)), Bound::Excluded(&key_of(31)))
+            .unwrap(),
+    );
+    assert_eq!(
+        result,
+        vec![
+            (key_of(18), value_of(18, 0)),
+            (key_of(19), value_of(19, 0)),
+            (key_of(30), value_of(30, 0)),
+        ]
+    );
+    assert!(!block_cached(&storage, &level[0]));
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::rate_limiter::{IoPriority, RateLimiter};
+
+#[test]
+fn test_flush_rate_limit() {
+    let dir = tempdir().unwrap();
+    let rate_limiter = Arc::new(RateLimiter::new(0));
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            rate_limiter: Some(rate_limiter.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..1000 {
+        storage
+            .put(format!("key_{:04}", idx).as_bytes(), &[b'x'; 100])
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    let file_size = storage.inner.read().l0_sstables[0].file_size();
+    assert!(file_size > 100_000);
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Flush), file_size);
+
+    // Limit the second flush to a third of the file per second, at runtime.
+    rate_limiter.set_bytes_per_second(file_size / 3);
+    rate_limiter.request(rate_limiter.single_burst_bytes(), IoPriority::Flush);
+    for idx in 0..1000 {
+        storage
+            .put(format!("key_{:04}", idx).as_bytes(), &[b'y'; 100])
+            .unwrap();
+    }
+    let start = Instant::now();
+    storage.sync().unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(2));
+    assert_eq!(rate_limiter.total_bytes(IoPriority::Compaction), 0);
+
+    // Foreground reads are not limited.
+    rate_limiter.set_bytes_per_second(1);
+    let start = Instant::now();
+    for idx in 0..1000 {
+        assert_eq!(
+            &storage
+                .get(format!("key_{:04}", idx).as_bytes())
+                .unwrap()
+                .unwrap()[..],
+            &[b'y'; 100]
+        );
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_flush_rate_limit_blob_files() {
+    let dir = tempdir().unwrap();
+    let rate_limiter = Arc::new(RateLimiter::new(0));
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            rate_limiter: Some(rate_limiter.clone()),
+            blob_threshold: Some(64),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..1000 {
+        storage
+            .put(format!("key_{:04}", idx).as_bytes(), &[b'x'; 100])
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    let snapshot = storage.inner.read();
+    let blob_size: u64 = snapshot
+        .blob_files
+        .values()
+        .map(|blob_file| blob_file.size())
+        .sum();
+    assert!(blob_size > 100_000);
+    // The blob file is written in the flush lane along with the table.
+    assert_eq!(
+        rate_limiter.total_bytes(IoPriority::Flush),
+        snapshot.l0_sstables[0].file_size() + blob_size
+    );
+}
@@ -0,0 +1,100 @@
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::row_cache::RowCache;
+use crate::tests::harness::{key_of, value_of};
+
+fn open_with_row_cache(dir: &tempfile::TempDir, capacity: u64) -> LsmStorage {
+    LsmStorage::open_with_options(
+        dir,
+        LsmStorageOptions {
+            row_cache_capacity: Some(capacity),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_hot_key_skips_sstables() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_row_cache(&dir, 1 << 20);
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.sync().unwrap();
+
+    assert_eq!(storage.get(&key_of(3)).unwrap(), Some(value_of(3, 0)));
+    let stats = storage.block_cache_stats();
+    for _ in 0..100 {
+        assert_eq!(storage.get(&key_of(3)).unwrap(), Some(value_of(3, 0)));
+    }
+    let after = storage.block_cache_stats();
+    assert_eq!(stats.hits + stats.misses, after.hits + after.misses);
+    assert_eq!(storage.row_cache.as_ref().unwrap().entry_count(), 1);
+}
+
+#[test]
+fn test_write_invalidates_row_cache() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_row_cache(&dir, 1 << 20);
+    // A missing key is cached as well.
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    storage.put(b"key", b"v1").unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v1")));
+    storage.sync().unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v1")));
+    storage.put(b"key", b"v2").unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v2")));
+    storage.sync().unwrap();
+    storage.delete(b"key").unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    storage.sync().unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    storage.put(b"key", b"v3").unwrap();
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v3")));
+}
+
+#[test]
+fn test_stale_fill_is_dropped() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_row_cache(&dir, 1 << 20);
+    let row_cache = storage.row_cache.as_ref().unwrap();
+    // A lookup starts reading the old value, and a write lands before it fills the cache.
+    let version = row_cache.version(b"key");
+    storage.put(b"key", b"new").unwrap();
+    row_cache.fill(b"key", version, Some(Bytes::from("old")));
+    assert_eq!(row_cache.get(b"key"), None);
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("new")));
+}
+
+#[test]
+fn test_row_cache_bounded_by_bytes() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_row_cache(&dir, 2048);
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in 0..100 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx, 0)));
+    }
+    let row_cache = storage.row_cache.as_ref().unwrap();
+    assert!(row_cache.size() <= 2048);
+    assert!(row_cache.entry_count() < 100);
+}
+
+#[test]
+fn test_row_cache_copies_values() {
+    let row_cache = RowCache::new(1 << 20);
+    // A small value sliced from a large buffer, like a value read from a cached block.
+    let block = Bytes::from(vec![b'x'; 1 << 16]);
+    let value = block.slice(100..110);
+    row_cache.fill(b"key", row_cache.version(b"key"), Some(value));
+    let cached = row_cache.get(b"key").unwrap().unwrap();
+    assert_eq!(cached, block.slice(100..110));
+    let block_range = block.as_ptr_range();
+    assert!(!block_range.contains(&cached.as_ptr()));
+    assert_eq!(row_cache.size(), 13);
+}
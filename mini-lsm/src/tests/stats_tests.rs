@@ -0,0 +1,137 @@
+use tempfile::tempdir;
+
+use crate::lsm_storage::{BulkLoadOptions, LsmStorage, LsmStorageOptions, NUM_LEVELS};
+use crate::tests::harness::{key_of, value_of};
+
+#[test]
+fn test_get_stats() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            block_size: 128,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in 100..110 {
+        storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+    }
+    storage.delete(&key_of(0)).unwrap();
+
+    let stats = storage.stats();
+    assert_eq!(stats.num_puts, 110);
+    assert_eq!(stats.num_deletes, 1);
+    assert_eq!(stats.write_latency.count, 111);
+    let user_bytes: usize = (0..110)
+        .map(|idx| key_of(idx).len() + value_of(idx, 0).len())
+        .sum();
+    assert_eq!(
+        stats.user_bytes_written,
+        (user_bytes + key_of(0).len()) as u64
+    );
+    assert_eq!(stats.num_flushes, 1);
+    assert_eq!(stats.flush_latency.count, 1);
+    assert_eq!(stats.levels.len(), NUM_LEVELS + 1);
+    assert_eq!(stats.levels[0].num_files, 1);
+    assert_eq!(stats.levels[0].size, stats.bytes_flushed);
+    assert_eq!(stats.levels[0].bytes_written, stats.bytes_flushed);
+    assert!(stats.write_amplification > 0.0);
+    let bytes_read = stats.levels[0].bytes_read;
+
+    // The key deleted in the memtable, 10 keys in the memtable, 10 in the SST, and 5 missing ones.
+    assert_eq!(storage.get(&key_of(0)).unwrap(), None);
+    for idx in 100..110 {
+        storage.get(&key_of(idx)).unwrap();
+    }
+    for idx in 10..20 {
+        storage.get(&key_of(idx)).unwrap();
+    }
+    let missing: Vec<_> = (200..205).map(key_of).collect();
+    let missing: Vec<&[u8]> = missing.iter().map(|key| &key[..]).collect();
+    assert_eq!(storage.multi_get(&missing).unwrap(), vec![None; 5]);
+    let stats = storage.stats();
+    assert_eq!(stats.num_gets, 26);
+    assert_eq!(stats.get_hits_memtable, 11);
+    assert_eq!(stats.get_hits_sst, 10);
+    assert_eq!(stats.get_misses, 5);
+    assert_eq!(stats.get_hits_row_cache, 0);
+    assert_eq!(stats.get_latency.count, 21);
+    assert_eq!(stats.multi_get_latency.count, 1);
+    assert!(stats.levels[0].bytes_read > bytes_read);
+    assert!(stats.block_cache.hits + stats.block_cache.misses > 0);
+
+    storage
+        .scan(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+        .unwrap();
+    storage.scan_prefix(b"key_00").unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.num_scans, 2);
+    assert_eq!(stats.scan_latency.count, 2);
+}
+
+#[test]
+fn test_row_cache_stats() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            row_cache_capacity: Some(1 << 20),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.sync().unwrap();
+    for _ in 0..3 {
+        storage.get(b"key").unwrap();
+    }
+    storage.multi_get(&[b"key", b"missing"]).unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.num_gets, 5);
+    assert_eq!(stats.get_hits_row_cache, 3);
+    assert_eq!(stats.get_hits_sst, 1);
+    assert_eq!(stats.get_misses, 1);
+}
+
+#[test]
+fn test_level_stats() {
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open(&dir).unwrap();
+    storage
+        .bulk_load(
+            (0..1000).map(|idx| (key_of(idx), value_of(idx, 0))),
+            &BulkLoadOptions {
+                memory_limit: 4 << 10,
+                target_sst_size: 4 << 10,
+            },
+        )
+        .unwrap();
+    storage.put(&key_of(0), b"new").unwrap();
+    storage.sync().unwrap();
+
+    let stats = storage.stats();
+    let bottom = &stats.levels[NUM_LEVELS];
+    assert!(bottom.num_files > 1);
+    assert_eq!(bottom.bytes_written, bottom.size);
+    assert_eq!(stats.bytes_ingested, bottom.size);
+    assert_eq!(stats.levels[0].num_files, 1);
+    assert!(stats.levels[1..NUM_LEVELS]
+        .iter()
+        .all(|level| level.num_files == 0 && level.bytes_written == 0));
+    // Only user writes and flushes count towards the write amplification.
+    assert_eq!(
+        stats.write_amplification,
+        stats.bytes_flushed as f64 / stats.user_bytes_written as f64
+    );
+
+    for idx in 0..1000 {
+        storage.get(&key_of(idx)).unwrap();
+    }
+    let stats = storage.stats();
+    assert!(stats.levels[NUM_LEVELS].bytes_read > stats.levels[NUM_LEVELS].size / 2);
+}
@@ -0,0 +1,107 @@
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::tests::harness::{key_of, value_of};
+
+/// Open a storage with `num_tables` L0 tables of 10 keys each.
+fn open_with_tables(
+    dir: &tempfile::TempDir,
+    max_open_files: usize,
+    num_tables: usize,
+) -> LsmStorage {
+    let storage = LsmStorage::open_with_options(
+        dir,
+        LsmStorageOptions {
+            max_open_files: Some(max_open_files),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for batch in 0..num_tables {
+        for idx in batch * 10..batch * 10 + 10 {
+            storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage
+}
+
+#[test]
+fn test_open_tables_bounded() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_tables(&dir, 2, 6);
+    assert!(storage.table_cache.entry_count() <= 2);
+    for idx in 0..60 {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx, 0)));
+        assert!(storage.table_cache.entry_count() <= 2);
+    }
+}
+
+#[test]
+fn test_table_opened_on_demand() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_tables(&dir, 10, 2);
+    let tables = storage.inner.read().l0_sstables.clone();
+    for table in &tables {
+        storage.table_cache.invalidate(table.sst_id());
+    }
+    assert_eq!(storage.get(&key_of(15)).unwrap(), Some(value_of(15, 0)));
+    assert!(!storage.table_cache.contains_key(tables[0].sst_id()));
+    assert!(storage.table_cache.contains_key(tables[1].sst_id()));
+}
+
+#[test]
+fn test_iterator_pins_table() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_tables(&dir, 1, 3);
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    // Close all tables while the iterator is still reading them.
+    for table in storage.inner.read().l0_sstables.iter() {
+        storage.table_cache.invalidate(table.sst_id());
+    }
+    assert_eq!(storage.table_cache.entry_count(), 0);
+    for idx in 0..30 {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx, 0));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_mmap_tables() {
+    for mmap_max_file_size in [u64::MAX, 0] {
+        let dir = tempdir().unwrap();
+        let storage = LsmStorage::open_with_options(
+            &dir,
+            LsmStorageOptions {
+                use_mmap: true,
+                mmap_max_file_size,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        for idx in 0..30 {
+            storage.put(&key_of(idx), &value_of(idx, 0)).unwrap();
+            if idx % 10 == 9 {
+                storage.sync().unwrap();
+            }
+        }
+        for idx in 0..30 {
+            assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx, 0)));
+        }
+        let mut iter = storage
+            .scan(Bound::Included(&key_of(5)), Bound::Excluded(&key_of(25)))
+            .unwrap();
+        for idx in 5..25 {
+            assert_eq!(iter.key(), key_of(idx));
+            assert_eq!(iter.value(), value_of(idx, 0));
+            iter.next().unwrap();
+        }
+        assert!(!iter.is_valid());
+    }
+}